@@ -0,0 +1,86 @@
+//! Commands run by prompts and status bars, on histories of different sizes.
+//!
+//! `status` reads the last period from the state sidecar, and `start` and `stop` only load the
+//! shard holding it. Their times depend on the size of that shard, which stops growing once a
+//! history spans more than a year, rather than on the size of the history.
+//!
+//! Run with `cargo bench`.
+
+extern crate chrono;
+extern crate criterion;
+extern crate doug;
+extern crate serde_json;
+extern crate tempfile;
+
+use std::fs;
+
+use chrono::{Duration, Utc};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use doug::Doug;
+use serde_json::json;
+use tempfile::TempDir;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+/// A data directory with `size` periods, ten a day up to now.
+///
+/// The periods are written in the single file layout, and split into shards when the data
+/// directory is first opened.
+fn history(size: usize) -> TempDir {
+    let folder = tempfile::tempdir().unwrap();
+    let now = Utc::now();
+    let periods: Vec<_> = (0..size)
+        .rev()
+        .map(|index| {
+            let start = now - Duration::minutes(144 * (index as i64 + 1));
+            json!({
+                "id": format!("{:07x}", index),
+                "project": format!("project-{}", index % 20),
+                "start_time": start,
+                "end_time": start + Duration::minutes(60),
+            })
+        })
+        .collect();
+    fs::write(
+        folder.path().join("periods.json"),
+        json!({ "version": 2, "periods": periods }).to_string(),
+    )
+    .unwrap();
+    let mut doug = Doug::new(Some(&folder.path().to_string_lossy())).unwrap();
+    doug.status(true, false).unwrap();
+    folder
+}
+
+fn status(c: &mut Criterion) {
+    let mut group = c.benchmark_group("status");
+    for size in SIZES {
+        let folder = history(size);
+        let path = folder.path().to_string_lossy().into_owned();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &path, |b, path| {
+            b.iter(|| Doug::new(Some(path)).unwrap().status(true, false).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn start_stop(c: &mut Criterion) {
+    let mut group = c.benchmark_group("start and stop");
+    group.sample_size(20);
+    for size in SIZES {
+        let folder = history(size);
+        let path = folder.path().to_string_lossy().into_owned();
+        group.bench_with_input(BenchmarkId::from_parameter(size), &path, |b, path| {
+            b.iter(|| {
+                Doug::new(Some(path))
+                    .unwrap()
+                    .start("bench", &[], None)
+                    .unwrap();
+                Doug::new(Some(path)).unwrap().stop(None).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, status, start_stop);
+criterion_main!(benches);
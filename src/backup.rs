@@ -0,0 +1,186 @@
+//! Rolling backups of the data file.
+//!
+//! Before the data file is replaced, a copy is saved to `backups/periods-<timestamp>.json` (or
+//! `.sqlite` or `.enc`, depending on the storage backend) in the data directory. Backups of an
+//! encrypted data file are encrypted as well. Old backups are removed
+//! according to [settings::Retention].
+//!
+//! With [storage::ShardedStorage](crate::storage::ShardedStorage) only the shards that change
+//! are backed up, to `backups/periods-<timestamp>.<shard>.json`. Retention applies to the
+//! backups of each shard separately.
+//!
+//! The journal is only ever appended to, so it's backed up before it's compacted rather than on
+//! every save. A SQLite database only has its changed rows written, so it's backed up before
+//! changes to many periods at once: `check --fix`, restoring a backup, or migrating into it.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+
+use crate::{settings, storage, Period};
+
+const PREFIX: &str = "periods-";
+const EXTENSIONS: [&str; 4] = ["json", "sqlite", "journal", "enc"];
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.6fZ";
+
+/// A backup of the data file
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub location: PathBuf,
+    pub created: DateTime<Utc>,
+    /// Shard the backup holds, if it isn't a backup of all periods
+    pub shard: Option<String>,
+}
+
+/// Directory where backups for a data directory are stored
+pub fn folder(data_folder: &Path) -> PathBuf {
+    data_folder.join("backups")
+}
+
+/// List backups, newest first.
+pub fn list(folder: &Path) -> Result<Vec<Backup>, String> {
+    if !folder.exists() {
+        return Ok(Vec::new());
+    }
+    let entries =
+        fs::read_dir(folder).map_err(|err| format!("Couldn't read backup folder: {:?}", err))?;
+    let mut backups: Vec<Backup> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let (rest, extension) = name.strip_prefix(PREFIX)?.rsplit_once('.')?;
+            if !EXTENSIONS.contains(&extension) {
+                return None;
+            }
+            let (created, shard) = match NaiveDateTime::parse_from_str(rest, TIMESTAMP_FORMAT) {
+                Ok(created) => (created, None),
+                Err(_) => {
+                    let (timestamp, shard) = rest.rsplit_once('.')?;
+                    let created =
+                        NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+                    (created, Some(shard.to_string()))
+                }
+            };
+            Some(Backup {
+                location: entry.path(),
+                created: Utc.from_utc_datetime(&created),
+                shard,
+            })
+        })
+        .collect();
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created));
+    Ok(backups)
+}
+
+/// Copy a data file into the backup folder.
+///
+/// Files not named `periods` are shards, named after the shard (e.g. `periods/2026.json`).
+pub fn create(folder: &Path, data_location: &Path) -> Result<Backup, String> {
+    fs::create_dir_all(folder)
+        .map_err(|err| format!("Couldn't create backup folder: {:?}", err))?;
+    let created = Utc::now();
+    let extension = data_location
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("json");
+    let shard = data_location
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .filter(|stem| *stem != "periods")
+        .map(str::to_string);
+    let location = location(folder, created, shard.as_deref(), extension);
+    fs::copy(data_location, &location)
+        .map_err(|err| format!("Couldn't create backup file: {:?}", err))?;
+    Ok(Backup {
+        location,
+        created,
+        shard,
+    })
+}
+
+/// Save periods as a backup of all periods, in the format of the data file from `settings`.
+///
+/// Used for copies of periods that aren't the data file, like the leftovers listed by
+/// [storage::leftovers]. `created` is when the copy was last changed, so retention treats it
+/// like a backup made back then.
+pub fn save(
+    folder: &Path,
+    periods: &[Period],
+    created: DateTime<Utc>,
+    settings: &settings::Settings,
+) -> Result<Backup, String> {
+    fs::create_dir_all(folder)
+        .map_err(|err| format!("Couldn't create backup folder: {:?}", err))?;
+    let extension = match settings.storage {
+        storage::StorageKind::Encrypted => "enc",
+        _ => "json",
+    };
+    let location = location(folder, created, None, extension);
+    storage::open_file(&location, settings)?.save(periods)?;
+    Ok(Backup {
+        location,
+        created,
+        shard: None,
+    })
+}
+
+fn location(
+    folder: &Path,
+    created: DateTime<Utc>,
+    shard: Option<&str>,
+    extension: &str,
+) -> PathBuf {
+    folder.join(format!(
+        "{}{}{}.{}",
+        PREFIX,
+        created.format(TIMESTAMP_FORMAT),
+        shard.map(|shard| format!(".{}", shard)).unwrap_or_default(),
+        extension
+    ))
+}
+
+/// Remove backups that aren't kept by the retention policy.
+pub fn prune(folder: &Path, retention: &settings::Retention) -> Result<(), String> {
+    let mut by_shard: BTreeMap<Option<String>, Vec<Backup>> = BTreeMap::new();
+    for backup in list(folder)? {
+        by_shard
+            .entry(backup.shard.clone())
+            .or_default()
+            .push(backup);
+    }
+    for backups in by_shard.values() {
+        prune_backups(backups, retention)?;
+    }
+    Ok(())
+}
+
+/// Remove backups of one data file that aren't kept by the retention policy.
+///
+/// The newest backup is always kept, even if the settings were edited to keep none.
+fn prune_backups(backups: &[Backup], retention: &settings::Retention) -> Result<(), String> {
+    let keep_last = retention.keep_last.max(1);
+    let mut keep: HashSet<usize> = (0..keep_last.min(backups.len())).collect();
+
+    if retention.keep_daily > 0 {
+        let today = Local::now().date();
+        let mut days = HashSet::new();
+        // backups are newest first, so the first backup of each day is the newest one
+        for (index, backup) in backups.iter().enumerate() {
+            let day = backup.created.with_timezone(&Local).date();
+            let age = today.signed_duration_since(day).num_days();
+            if age < retention.keep_daily as i64 && days.insert(day) {
+                keep.insert(index);
+            }
+        }
+    }
+
+    for (index, backup) in backups.iter().enumerate() {
+        if !keep.contains(&index) {
+            fs::remove_file(&backup.location)
+                .map_err(|err| format!("Couldn't remove old backup: {:?}", err))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,514 @@
+//! Hourly rates and billable amounts for `doug report --billing`.
+//!
+//! Rates are set per project or per tag in [settings::Settings::rates](crate::settings::Settings::rates).
+//! Amounts are kept in hundredths of the currency unit, so sums are exact. Each line of a bill
+//! is rounded to the nearest hundredth once, and the total is the sum of the lines.
+//!
+//! Periods tagged [NON_BILLABLE_TAG] are listed separately, without a rate.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+
+use chrono::{Date, Duration, Local};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::report::{self, Format};
+use crate::Period;
+
+/// Tag marking periods that aren't billed
+pub const NON_BILLABLE_TAG: &str = "non-billable";
+
+/// An exact amount of money, or an hourly rate, with two decimal places
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Money {
+    hundredths: i64,
+}
+
+impl Money {
+    /// Amount for working `duration` at an hourly rate, rounded to the nearest hundredth
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate chrono;
+    /// # extern crate doug;
+    /// use chrono::Duration;
+    /// use doug::billing::Money;
+    ///
+    /// let rate: Money = "120.50".parse().unwrap();
+    /// assert_eq!(rate.for_time(Duration::minutes(90)).to_string(), "180.75");
+    /// assert_eq!(rate.for_time(Duration::seconds(10)).to_string(), "0.33");
+    /// ```
+    pub fn for_time(self, duration: Duration) -> Money {
+        Money {
+            hundredths: divide_rounded(
+                i128::from(self.hundredths) * i128::from(duration.num_seconds()),
+                3600,
+            ) as i64,
+        }
+    }
+}
+
+/// Divide, rounding halves away from zero
+fn divide_rounded(dividend: i128, divisor: i128) -> i128 {
+    let rounded = (dividend.abs() + divisor / 2) / divisor;
+    if dividend < 0 {
+        -rounded
+    } else {
+        rounded
+    }
+}
+
+impl std::ops::Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money {
+            hundredths: self.hundredths + other.hundredths,
+        }
+    }
+}
+
+impl std::str::FromStr for Money {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid amount {}. Use e.g. 120 or 97.50.", s);
+        let (units, decimals) = s.split_once('.').unwrap_or((s, ""));
+        if units.is_empty()
+            || decimals.len() > 2
+            || !units.bytes().all(|byte| byte.is_ascii_digit())
+            || !decimals.bytes().all(|byte| byte.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let units: i64 = units.parse().map_err(|_| invalid())?;
+        let decimals: i64 = format!("{:0<2}", decimals).parse().map_err(|_| invalid())?;
+        units
+            .checked_mul(100)
+            .and_then(|hundredths| hundredths.checked_add(decimals))
+            .map(|hundredths| Money { hundredths })
+            .ok_or_else(invalid)
+    }
+}
+
+impl TryFrom<String> for Money {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Money> for String {
+    fn from(money: Money) -> String {
+        money.to_string()
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.hundredths < 0 { "-" } else { "" };
+        let hundredths = self.hundredths.abs();
+        write!(f, "{}{}.{:02}", sign, hundredths / 100, hundredths % 100)
+    }
+}
+
+impl fmt::Debug for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Hours in `duration`, rounded to two decimal places (e.g. `1.50`)
+pub fn hours(duration: Duration) -> String {
+    let hundredths = divide_rounded(i128::from(duration.num_seconds()), 36);
+    format!("{}.{:02}", hundredths / 100, hundredths % 100)
+}
+
+/// Hourly rates of projects and tags
+#[derive(Eq, PartialEq, Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Rates {
+    /// Currency of the rates, e.g. `EUR`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Rates of projects. Subprojects without a rate of their own use their parent's rate.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub projects: BTreeMap<String, Money>,
+    /// Rates of tags, without the `+`. They take precedence over project rates, and the
+    /// highest applies to periods with several rated tags.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, Money>,
+}
+
+impl Rates {
+    pub fn is_empty(&self) -> bool {
+        *self == Rates::default()
+    }
+
+    /// Hourly rate of a period, `None` if neither its tags nor its project have one
+    pub fn rate(&self, period: &Period) -> Option<Money> {
+        let tag_rate = period
+            .tags
+            .iter()
+            .filter_map(|tag| self.tags.get(tag))
+            .max();
+        if let Some(rate) = tag_rate {
+            return Some(*rate);
+        }
+        let mut project = period.project.as_str();
+        loop {
+            if let Some(rate) = self.projects.get(project) {
+                return Some(*rate);
+            }
+            project = &project[..project.rfind('/')?];
+        }
+    }
+}
+
+/// Time on a project at one rate
+#[derive(Debug, Clone)]
+pub struct Line {
+    /// Full project name
+    pub project: String,
+    /// `None` for non-billable time, or if no rate is set
+    pub rate: Option<Money>,
+    pub duration: Duration,
+}
+
+impl Line {
+    pub fn amount(&self) -> Option<Money> {
+        self.rate.map(|rate| rate.for_time(self.duration))
+    }
+}
+
+/// Billable and non-billable time per project
+#[derive(Debug, Clone)]
+pub struct Bill {
+    pub from: Date<Local>,
+    pub to: Date<Local>,
+    pub currency: Option<String>,
+    /// Lines of billable time, by project and rate
+    pub billable: Vec<Line>,
+    /// Lines of time tagged [NON_BILLABLE_TAG], by project
+    pub non_billable: Vec<Line>,
+}
+
+impl Bill {
+    /// Sum up `periods` per project and rate.
+    pub fn new(periods: &[Period], rates: &Rates, from: Date<Local>, to: Date<Local>) -> Self {
+        let mut billable: HashMap<(String, Option<Money>), Duration> = HashMap::new();
+        let mut non_billable: HashMap<(String, Option<Money>), Duration> = HashMap::new();
+        for period in periods {
+            let duration = period.end_or_now().signed_duration_since(period.start_time);
+            let (lines, rate) = if period.tags.iter().any(|tag| tag == NON_BILLABLE_TAG) {
+                (&mut non_billable, None)
+            } else {
+                (&mut billable, rates.rate(period))
+            };
+            let total = lines
+                .entry((period.project.clone(), rate))
+                .or_insert_with(Duration::zero);
+            *total = *total + duration;
+        }
+        let lines = |lines: HashMap<(String, Option<Money>), Duration>| {
+            let mut lines: Vec<Line> = lines
+                .into_iter()
+                .map(|((project, rate), duration)| Line {
+                    project,
+                    rate,
+                    duration,
+                })
+                .collect();
+            lines.sort_by(|a, b| (&a.project, a.rate).cmp(&(&b.project, b.rate)));
+            lines
+        };
+        Bill {
+            from,
+            to,
+            currency: rates.currency.clone(),
+            billable: lines(billable),
+            non_billable: lines(non_billable),
+        }
+    }
+
+    /// Billable time, and the sum of the amounts of its lines
+    pub fn total(&self) -> (Duration, Money) {
+        self.billable.iter().fold(
+            (Duration::zero(), Money::default()),
+            |(time, money), line| {
+                (
+                    time + line.duration,
+                    money + line.amount().unwrap_or_default(),
+                )
+            },
+        )
+    }
+
+    pub fn non_billable_total(&self) -> Duration {
+        self.non_billable
+            .iter()
+            .fold(Duration::zero(), |time, line| time + line.duration)
+    }
+
+    /// Projects with billable time but no rate
+    fn unrated(&self) -> Vec<&str> {
+        self.billable
+            .iter()
+            .filter(|line| line.rate.is_none())
+            .map(|line| line.project.as_str())
+            .collect()
+    }
+}
+
+/// Render a bill in one of the report formats.
+pub fn render(bill: &Bill, format: Format) -> Result<String, String> {
+    match format {
+        Format::Text => Ok(text(bill)),
+        Format::Json => serde_json::to_string_pretty(&json(bill))
+            .map(|json| json + "\n")
+            .map_err(|_| "Couldn't serialize data to string".to_string()),
+        Format::Csv => Ok(csv(bill)),
+        Format::Markdown => Ok(markdown(bill)),
+    }
+}
+
+fn optional(money: Option<Money>) -> String {
+    money.map_or_else(|| "-".to_string(), |money| money.to_string())
+}
+
+/// Aligned tables of billable and non-billable time, with a warning for missing rates
+fn text(bill: &Bill) -> String {
+    let currency = bill
+        .currency
+        .as_ref()
+        .map_or_else(String::new, |currency| format!(" ({})", currency));
+    let (total_time, total_amount) = bill.total();
+    let mut rows: Vec<[String; 4]> = vec![[
+        "Billable".to_string(),
+        "Hours".to_string(),
+        format!("Rate{}", currency),
+        format!("Amount{}", currency),
+    ]];
+    for line in &bill.billable {
+        rows.push([
+            line.project.clone(),
+            hours(line.duration),
+            optional(line.rate),
+            optional(line.amount()),
+        ]);
+    }
+    rows.push([
+        "Total".to_string(),
+        hours(total_time),
+        String::new(),
+        total_amount.to_string(),
+    ]);
+    let non_billable = rows.len();
+    if !bill.non_billable.is_empty() {
+        rows.push([
+            "Non-billable".to_string(),
+            "Hours".to_string(),
+            String::new(),
+            String::new(),
+        ]);
+        for line in &bill.non_billable {
+            rows.push([
+                line.project.clone(),
+                hours(line.duration),
+                String::new(),
+                String::new(),
+            ]);
+        }
+        rows.push([
+            "Total".to_string(),
+            hours(bill.non_billable_total()),
+            String::new(),
+            String::new(),
+        ]);
+    }
+    let widths: Vec<usize> = (0..4)
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect();
+
+    let mut message = format!(
+        "{start} -> {end}\n",
+        start = bill.from.format("%A %-d %B %Y").to_string().blue(),
+        end = bill.to.format("%A %-d %B %Y").to_string().blue()
+    );
+    for (index, row) in rows.iter().enumerate() {
+        if index == non_billable {
+            message.push('\n');
+        }
+        let heading = index == 0 || index == non_billable;
+        let total = row[0] == "Total" && !heading;
+        let name = format!("{:width$}", row[0], width = widths[0]);
+        let name = if heading || total {
+            name.bold()
+        } else {
+            name.green()
+        };
+        let cells = format!(
+            "  {:>w1$}  {:>w2$}  {:>w3$}",
+            row[1],
+            row[2],
+            row[3],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3]
+        );
+        let cells = if heading {
+            cells.blue()
+        } else {
+            cells.normal()
+        };
+        message.push_str(format!("{}{}", name, cells).trim_end());
+        message.push('\n');
+    }
+    let unrated = bill.unrated();
+    if !unrated.is_empty() {
+        message.push_str(&format!(
+            "\n{} {}. Set one with doug settings --rate.\n",
+            "No rate for".yellow(),
+            unrated.join(", ")
+        ));
+    }
+    message
+}
+
+fn json_line(line: &Line) -> Value {
+    json!({
+        "project": line.project,
+        "seconds": line.duration.num_seconds(),
+        "hours": hours(line.duration),
+        "rate": line.rate.map(String::from),
+        "amount": line.amount().map(String::from),
+    })
+}
+
+/// Amounts and hours are strings, so they stay exact
+fn json(bill: &Bill) -> Value {
+    let (total_time, total_amount) = bill.total();
+    let non_billable_total = bill.non_billable_total();
+    json!({
+        "from": bill.from.format("%F").to_string(),
+        "to": bill.to.format("%F").to_string(),
+        "currency": bill.currency,
+        "billable": bill.billable.iter().map(json_line).collect::<Vec<_>>(),
+        "total": {
+            "seconds": total_time.num_seconds(),
+            "hours": hours(total_time),
+            "amount": String::from(total_amount),
+        },
+        "non_billable": bill.non_billable.iter().map(json_line).collect::<Vec<_>>(),
+        "non_billable_total": {
+            "seconds": non_billable_total.num_seconds(),
+            "hours": hours(non_billable_total),
+        },
+    })
+}
+
+/// One line per project and rate with its section, and a total line per section. The
+/// `row_type` column is `line` for projects and `total` for the total lines, which have no
+/// project.
+fn csv(bill: &Bill) -> String {
+    let from = bill.from.format("%F");
+    let to = bill.to.format("%F");
+    let currency = report::csv_field(bill.currency.as_deref().unwrap_or(""));
+    let mut csv =
+        "row_type,section,project,seconds,hours,rate,amount,currency,from,to\n".to_string();
+    let mut push =
+        |section: &str, project: Option<&str>, time: Duration, rate: String, amount: String| {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{},{}\n",
+                if project.is_some() { "line" } else { "total" },
+                section,
+                report::csv_field(project.unwrap_or_default()),
+                time.num_seconds(),
+                hours(time),
+                rate,
+                amount,
+                currency,
+                from,
+                to
+            ));
+        };
+    let (total_time, total_amount) = bill.total();
+    for line in &bill.billable {
+        let rate = line.rate.map(String::from).unwrap_or_default();
+        let amount = line.amount().map(String::from).unwrap_or_default();
+        push("billable", Some(&line.project), line.duration, rate, amount);
+    }
+    push(
+        "billable",
+        None,
+        total_time,
+        String::new(),
+        total_amount.to_string(),
+    );
+    for line in &bill.non_billable {
+        push(
+            "non-billable",
+            Some(&line.project),
+            line.duration,
+            String::new(),
+            String::new(),
+        );
+    }
+    push(
+        "non-billable",
+        None,
+        bill.non_billable_total(),
+        String::new(),
+        String::new(),
+    );
+    csv
+}
+
+/// A table of billable time with a bold total row, and one of non-billable time if there is any
+fn markdown(bill: &Bill) -> String {
+    let currency = bill
+        .currency
+        .as_ref()
+        .map_or_else(String::new, |currency| format!(" ({})", currency));
+    let (total_time, total_amount) = bill.total();
+    let mut markdown = format!(
+        "{} to {}\n\n| Project | Hours | Rate{currency} | Amount{currency} |\n| --- | ---: | ---: | ---: |\n",
+        bill.from.format("%F"),
+        bill.to.format("%F"),
+        currency = currency
+    );
+    for line in &bill.billable {
+        markdown.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            report::markdown_cell(&line.project),
+            hours(line.duration),
+            optional(line.rate),
+            optional(line.amount())
+        ));
+    }
+    markdown.push_str(&format!(
+        "| **Total** | **{}** | | **{}** |\n",
+        hours(total_time),
+        total_amount
+    ));
+    if !bill.non_billable.is_empty() {
+        markdown.push_str("\n| Non-billable | Hours |\n| --- | ---: |\n");
+        for line in &bill.non_billable {
+            markdown.push_str(&format!(
+                "| {} | {} |\n",
+                report::markdown_cell(&line.project),
+                hours(line.duration)
+            ));
+        }
+        markdown.push_str(&format!(
+            "| **Total** | **{}** |\n",
+            hours(bill.non_billable_total())
+        ));
+    }
+    markdown
+}
@@ -0,0 +1,184 @@
+//! Calendar arithmetic for report ranges.
+//!
+//! Weeks start on [settings::Settings::first_weekday](crate::settings::Settings::first_weekday),
+//! except ISO weeks, which always start on Monday.
+
+use chrono::{Date, Datelike, Duration, Local, NaiveDate, TimeZone, Weekday};
+
+/// First day of the week holding `date`
+pub fn week_start(date: Date<Local>, first_weekday: Weekday) -> Date<Local> {
+    let days =
+        (7 + date.weekday().num_days_from_monday() - first_weekday.num_days_from_monday()) % 7;
+    date - Duration::days(days.into())
+}
+
+/// First day of the month holding `date`
+pub fn month_start(date: Date<Local>) -> Date<Local> {
+    Local.ymd(date.year(), date.month(), 1)
+}
+
+/// Move `date` by a number of calendar months, keeping the day of the month where possible.
+///
+/// Days past the end of the new month become its last day.
+///
+/// # Examples
+/// ```
+/// # extern crate chrono;
+/// # extern crate doug;
+/// use chrono::{Local, TimeZone};
+/// use doug::calendar::add_months;
+///
+/// assert_eq!(add_months(Local.ymd(2026, 10, 16), -1), Local.ymd(2026, 9, 16));
+/// assert_eq!(add_months(Local.ymd(2026, 3, 31), -1), Local.ymd(2026, 2, 28));
+/// assert_eq!(add_months(Local.ymd(2026, 10, 16), -12), Local.ymd(2025, 10, 16));
+/// ```
+pub fn add_months(date: Date<Local>, months: i32) -> Date<Local> {
+    let months = date.year() * 12 + date.month0() as i32 + months;
+    let (year, month) = (months.div_euclid(12), months.rem_euclid(12) as u32 + 1);
+    let day = date.day().min(days_in_month(year, month));
+    Local.ymd(year, month, day)
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let next = match month {
+        12 => NaiveDate::from_ymd(year + 1, 1, 1),
+        _ => NaiveDate::from_ymd(year, month + 1, 1),
+    };
+    next.pred().day()
+}
+
+/// A calendar period to report on
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum NamedRange {
+    ThisWeek,
+    LastWeek,
+    ThisMonth,
+    LastMonth,
+    /// Quarter of a year, from 1 to 4
+    Quarter {
+        year: i32,
+        quarter: u32,
+    },
+    /// ISO 8601 week of a year
+    IsoWeek {
+        year: i32,
+        week: u32,
+    },
+    Month {
+        year: i32,
+        month: u32,
+    },
+}
+
+/// Check that a range of months starting in `year` and `month` can be represented, including
+/// the first day after it, which can be in the next year.
+fn months_exist(year: i32, month: u32) -> bool {
+    NaiveDate::from_ymd_opt(year, month, 1).is_some()
+        && year
+            .checked_add(1)
+            .and_then(|next| NaiveDate::from_ymd_opt(next, 1, 1))
+            .is_some()
+}
+
+/// Split `2026-Q3` style text into the year and the number after `separator`.
+fn year_and_number(text: &str, separator: char) -> Option<(i32, u32)> {
+    let (year, number) = text.split_once('-')?;
+    let number = number
+        .strip_prefix(separator)
+        .or_else(|| number.strip_prefix(separator.to_ascii_lowercase()))?;
+    Some((year.parse().ok()?, number.parse().ok()?))
+}
+
+impl NamedRange {
+    /// Parse a quarter like `2026-Q3`.
+    pub fn quarter(text: &str) -> Result<Self, String> {
+        match year_and_number(text, 'Q') {
+            Some((year, quarter))
+                if (1..=4).contains(&quarter) && months_exist(year, quarter * 3 - 2) =>
+            {
+                Ok(NamedRange::Quarter { year, quarter })
+            }
+            _ => Err(format!("Invalid quarter {}. Use e.g. 2026-Q3.", text)),
+        }
+    }
+
+    /// Parse an ISO week like `2026-W41`.
+    pub fn iso_week(text: &str) -> Result<Self, String> {
+        match year_and_number(text, 'W') {
+            Some((year, week))
+                if NaiveDate::from_isoywd_opt(year, week, Weekday::Mon).is_some()
+                    && NaiveDate::from_isoywd_opt(year, week, Weekday::Sun).is_some() =>
+            {
+                Ok(NamedRange::IsoWeek { year, week })
+            }
+            _ => Err(format!("Invalid ISO week {}. Use e.g. 2026-W41.", text)),
+        }
+    }
+
+    /// Parse a month like `2026-09`.
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate doug;
+    /// use doug::calendar::NamedRange;
+    ///
+    /// assert_eq!(
+    ///     NamedRange::month("2026-09"),
+    ///     Ok(NamedRange::Month { year: 2026, month: 9 })
+    /// );
+    /// assert!(NamedRange::month("2026-13").is_err());
+    /// assert!(NamedRange::month("999999-01").is_err());
+    /// ```
+    pub fn month(text: &str) -> Result<Self, String> {
+        let month = text
+            .split_once('-')
+            .and_then(|(year, month)| Some((year.parse().ok()?, month.parse().ok()?)));
+        match month {
+            Some((year, month)) if (1..=12).contains(&month) && months_exist(year, month) => {
+                Ok(NamedRange::Month { year, month })
+            }
+            _ => Err(format!("Invalid month {}. Use e.g. 2026-09.", text)),
+        }
+    }
+
+    /// First and last day of the range
+    ///
+    /// # Arguments
+    /// * `today` — day that `this` and `last` ranges are relative to
+    /// * `first_weekday` — day that `this` and `last` weeks start on
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate chrono;
+    /// # extern crate doug;
+    /// use chrono::{Local, TimeZone, Weekday};
+    /// use doug::calendar::NamedRange;
+    ///
+    /// let today = Local.ymd(2026, 10, 16);
+    /// assert_eq!(
+    ///     NamedRange::LastWeek.dates(today, Weekday::Sun),
+    ///     (Local.ymd(2026, 10, 4), Local.ymd(2026, 10, 10))
+    /// );
+    /// assert_eq!(
+    ///     NamedRange::quarter("2026-Q3").unwrap().dates(today, Weekday::Mon),
+    ///     (Local.ymd(2026, 7, 1), Local.ymd(2026, 9, 30))
+    /// );
+    /// ```
+    pub fn dates(self, today: Date<Local>, first_weekday: Weekday) -> (Date<Local>, Date<Local>) {
+        let week = |start: Date<Local>| (start, start + Duration::days(6));
+        let months =
+            |start: Date<Local>, count: i32| (start, add_months(start, count) - Duration::days(1));
+        match self {
+            NamedRange::ThisWeek => week(week_start(today, first_weekday)),
+            NamedRange::LastWeek => week(week_start(today, first_weekday) - Duration::weeks(1)),
+            NamedRange::ThisMonth => months(month_start(today), 1),
+            NamedRange::LastMonth => months(add_months(month_start(today), -1), 1),
+            NamedRange::Quarter { year, quarter } => months(Local.ymd(year, quarter * 3 - 2, 1), 3),
+            NamedRange::IsoWeek { year, week: number } => {
+                let monday = NaiveDate::from_isoywd(year, number, Weekday::Mon);
+                week(Local.from_local_date(&monday).unwrap())
+            }
+            NamedRange::Month { year, month } => months(Local.ymd(year, month, 1), 1),
+        }
+    }
+}
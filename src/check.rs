@@ -0,0 +1,198 @@
+//! Validation and repair of period data.
+//!
+//! Periods can get into an inconsistent state when the data file is edited by hand, or after
+//! merging files from different machines.
+
+use std::fmt;
+
+use crate::Period;
+
+/// An inconsistency in the period data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// More than one period is running
+    MultipleOpen(Vec<String>),
+    /// A running period is followed by other periods
+    OpenNotLast(String),
+    /// A period ends before it starts
+    EndBeforeStart(String),
+    /// Two periods share some time
+    Overlap(String, String),
+    /// Periods start at exactly the same time
+    DuplicateStart(Vec<String>),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::MultipleOpen(ids) => {
+                write!(f, "{} periods are running: {}", ids.len(), ids.join(", "))
+            }
+            Problem::OpenNotLast(id) => write!(f, "period {} is running but isn't last", id),
+            Problem::EndBeforeStart(id) => write!(f, "period {} ends before it starts", id),
+            Problem::Overlap(first, second) => {
+                write!(f, "periods {} and {} overlap", first, second)
+            }
+            Problem::DuplicateStart(ids) => {
+                write!(f, "periods {} start at the same time", ids.join(", "))
+            }
+        }
+    }
+}
+
+impl Problem {
+    /// How to repair the problem by hand, for problems [fix] leaves alone
+    pub fn hint(&self) -> String {
+        match self {
+            Problem::MultipleOpen(_) => {
+                "stop all but one of them with doug edit <id> --end".to_string()
+            }
+            Problem::OpenNotLast(id) => format!("set its end with doug edit {} --end", id),
+            Problem::EndBeforeStart(id) => {
+                format!("set its start and end with doug edit {} --start --end", id)
+            }
+            Problem::Overlap(first, second) => format!(
+                "shorten one with doug edit {} --end or doug edit {} --start, or remove one with doug delete --id",
+                first, second
+            ),
+            Problem::DuplicateStart(_) => {
+                "change the start of all but one with doug edit <id> --start, or remove them with doug delete --id"
+                    .to_string()
+            }
+        }
+    }
+}
+
+/// Find all problems in a list of periods.
+pub fn check(periods: &[Period]) -> Vec<Problem> {
+    let mut periods: Vec<&Period> = periods.iter().collect();
+    periods.sort_by_key(|period| period.start_time);
+    let mut problems = Vec::new();
+
+    let open: Vec<&Period> = periods
+        .iter()
+        .filter(|period| period.end_time.is_none())
+        .cloned()
+        .collect();
+    if open.len() > 1 {
+        problems.push(Problem::MultipleOpen(
+            open.iter().map(|period| period.id.clone()).collect(),
+        ));
+    }
+    if let Some((last, rest)) = periods.split_last() {
+        for period in rest.iter().filter(|period| period.end_time.is_none()) {
+            // a running period at the same start as the last one is only a duplicate
+            if period.start_time != last.start_time {
+                problems.push(Problem::OpenNotLast(period.id.clone()));
+            }
+        }
+    }
+
+    for period in &periods {
+        if period
+            .end_time
+            .is_some_and(|end_time| end_time < period.start_time)
+        {
+            problems.push(Problem::EndBeforeStart(period.id.clone()));
+        }
+    }
+
+    let mut index = 0;
+    while index < periods.len() {
+        let same_start: Vec<String> = periods[index..]
+            .iter()
+            .take_while(|period| period.start_time == periods[index].start_time)
+            .map(|period| period.id.clone())
+            .collect();
+        index += same_start.len();
+        if same_start.len() > 1 {
+            problems.push(Problem::DuplicateStart(same_start));
+        }
+    }
+
+    for (i, first) in periods.iter().enumerate() {
+        for second in &periods[i + 1..] {
+            if second.start_time >= first.end_or_now() {
+                break;
+            }
+            if first.start_time != second.start_time && first.overlaps(second) {
+                problems.push(Problem::Overlap(first.id.clone(), second.id.clone()));
+            }
+        }
+    }
+    problems
+}
+
+/// Apply repairs that don't lose tracked time, returning a description of each repair.
+///
+/// * exact duplicates of a period are removed
+/// * periods that end before they start have their start and end swapped
+/// * running periods that aren't last are stopped when the next period starts
+/// * a period that runs into the next one is cut short when the next one starts
+///
+/// Problems that need a decision, like a period contained in another, are left alone.
+pub fn fix(periods: &mut Vec<Period>) -> Vec<String> {
+    let mut repairs = Vec::new();
+    periods.sort_by_key(|period| period.start_time);
+
+    let mut deduped: Vec<Period> = Vec::with_capacity(periods.len());
+    for period in periods.drain(..) {
+        // periods are sorted, so duplicates are at the end of `deduped`
+        let duplicate = deduped
+            .iter()
+            .rev()
+            .take_while(|kept| kept.start_time == period.start_time)
+            .find(|kept| same_content(kept, &period));
+        match duplicate {
+            Some(kept) => {
+                repairs.push(format!("removed {}, a duplicate of {}", period.id, kept.id))
+            }
+            None => deduped.push(period),
+        }
+    }
+    *periods = deduped;
+
+    for period in periods.iter_mut() {
+        if let Some(end_time) = period.end_time {
+            if end_time < period.start_time {
+                period.end_time = Some(period.start_time);
+                period.start_time = end_time;
+                repairs.push(format!("swapped start and end of {}", period.id));
+            }
+        }
+    }
+    periods.sort_by_key(|period| period.start_time);
+
+    for index in 1..periods.len() {
+        let next_start = periods[index].start_time;
+        let next_end = periods[index].end_or_now();
+        let period = &mut periods[index - 1];
+        if period.start_time == next_start {
+            continue;
+        }
+        match period.end_time {
+            None => {
+                period.end_time = Some(next_start);
+                repairs.push(format!(
+                    "stopped {} when the next period started",
+                    period.id
+                ));
+            }
+            Some(end_time) if end_time > next_start && end_time <= next_end => {
+                period.end_time = Some(next_start);
+                repairs.push(format!("ended {} when the next period started", period.id));
+            }
+            _ => {}
+        }
+    }
+    repairs
+}
+
+/// Check if two periods only differ by id
+fn same_content(a: &Period, b: &Period) -> bool {
+    a.project == b.project
+        && a.start_time == b.start_time
+        && a.end_time == b.end_time
+        && a.tags == b.tags
+        && a.note == b.note
+}
@@ -0,0 +1,100 @@
+//! Encryption of data files at rest.
+//!
+//! Encrypted files start with a short header, followed by a random salt and nonce and the
+//! ChaCha20-Poly1305 ciphertext. The key is derived from a passphrase with Argon2id, so the
+//! same passphrase can open files on any machine.
+//!
+//! The passphrase is read from the key file in [settings::Settings::key_file] or, if none is
+//! set, from the `DOUG_PASSPHRASE` environment variable.
+
+use std::env;
+use std::fs;
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use crate::settings;
+
+/// Environment variable holding the passphrase
+pub const PASSPHRASE_VARIABLE: &str = "DOUG_PASSPHRASE";
+
+/// Start of every encrypted file
+const MAGIC: &[u8] = b"doug-encrypted-1\n";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+
+/// Find the passphrase configured in settings or the environment.
+pub fn passphrase(settings: &settings::Settings) -> Result<String, String> {
+    let passphrase = match &settings.key_file {
+        Some(key_file) => fs::read_to_string(key_file)
+            .map_err(|err| format!("Couldn't read key file {:?}: {:?}", key_file, err))?,
+        None => env::var(PASSPHRASE_VARIABLE).map_err(|_| {
+            format!(
+                "No passphrase for encrypted data. Set {} or a key file with doug settings --key-file.",
+                PASSPHRASE_VARIABLE
+            )
+        })?,
+    };
+    let passphrase = passphrase.trim_end_matches(['\r', '\n']).to_string();
+    if passphrase.is_empty() {
+        return Err("The passphrase is empty".to_string());
+    }
+    Ok(passphrase)
+}
+
+/// Check if data was written by [encrypt].
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypt data with a key derived from `passphrase`.
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    let mut salt = [0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let cipher = cipher(passphrase, &salt)?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| "Couldn't encrypt data".to_string())?;
+
+    let mut encrypted =
+        Vec::with_capacity(MAGIC.len() + SALT_LENGTH + NONCE_LENGTH + ciphertext.len());
+    encrypted.extend_from_slice(MAGIC);
+    encrypted.extend_from_slice(&salt);
+    encrypted.extend_from_slice(&nonce);
+    encrypted.extend_from_slice(&ciphertext);
+    Ok(encrypted)
+}
+
+/// Decrypt data written by [encrypt].
+///
+/// # Examples
+/// ```
+/// # extern crate doug;
+/// use doug::crypto::{decrypt, encrypt};
+///
+/// let encrypted = encrypt(b"client work", "hunter2").unwrap();
+/// assert_eq!(decrypt(&encrypted, "hunter2").unwrap(), b"client work");
+/// assert!(decrypt(&encrypted, "wrong").is_err());
+/// ```
+pub fn decrypt(encrypted: &[u8], passphrase: &str) -> Result<Vec<u8>, String> {
+    let body = encrypted
+        .strip_prefix(MAGIC)
+        .filter(|body| body.len() >= SALT_LENGTH + NONCE_LENGTH)
+        .ok_or_else(|| "Data isn't encrypted by doug".to_string())?;
+    let (salt, body) = body.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = body.split_at(NONCE_LENGTH);
+    cipher(passphrase, salt)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Couldn't decrypt data. Check the passphrase.".to_string())
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305, String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| format!("Couldn't derive key from passphrase: {}", err))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
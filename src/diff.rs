@@ -0,0 +1,218 @@
+//! Differences between two lists of periods.
+//!
+//! Periods are matched by their start time, so the same period from two data files is
+//! compared even if it was given a different id. Several periods can start at the same time;
+//! those are matched by content first, then by id.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use colored::*;
+use serde_json::{json, Value};
+
+use crate::Period;
+
+/// Differences between a `left` and a `right` list of periods
+#[derive(Debug, Default)]
+pub struct Diff {
+    /// Periods only in the left list
+    pub only_left: Vec<Period>,
+    /// Periods only in the right list
+    pub only_right: Vec<Period>,
+    /// Periods starting at the same time with a different end, project, tags or note
+    pub changed: Vec<(Period, Period)>,
+    /// Periods from the left and right list that share some time, where at least one of them
+    /// differs
+    pub overlaps: Vec<(Period, Period)>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.only_left.is_empty()
+            && self.only_right.is_empty()
+            && self.changed.is_empty()
+            && self.overlaps.is_empty()
+    }
+}
+
+/// Compare two lists of periods.
+pub fn diff(left: &[Period], right: &[Period]) -> Diff {
+    let mut by_start: BTreeMap<DateTime<Utc>, (Vec<&Period>, Vec<&Period>)> = BTreeMap::new();
+    for period in left {
+        by_start
+            .entry(period.start_time)
+            .or_default()
+            .0
+            .push(period);
+    }
+    for period in right {
+        by_start
+            .entry(period.start_time)
+            .or_default()
+            .1
+            .push(period);
+    }
+
+    let mut diff = Diff::default();
+    for (left_periods, right_periods) in by_start.values() {
+        for pair in pair(left_periods, right_periods) {
+            match pair {
+                (Some(left), Some(right)) if !same(left, right) => {
+                    diff.changed.push((left.clone(), right.clone()))
+                }
+                (Some(_), Some(_)) => {}
+                (Some(left), None) => diff.only_left.push(left.clone()),
+                (None, Some(right)) => diff.only_right.push(right.clone()),
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    // a differing period on one side against every period on the other side
+    let differing_left: Vec<&Period> = diff
+        .only_left
+        .iter()
+        .chain(diff.changed.iter().map(|(left, _)| left))
+        .collect();
+    let differing_right: Vec<&Period> = diff
+        .only_right
+        .iter()
+        .chain(diff.changed.iter().map(|(_, right)| right))
+        .collect();
+    // a changed period is compared to its own counterpart already
+    let partners = |left: &Period, right: &Period| {
+        diff.changed
+            .iter()
+            .any(|(changed_left, changed_right)| changed_left == left && changed_right == right)
+    };
+    let mut overlaps = Vec::new();
+    for left_period in &differing_left {
+        for right_period in right {
+            if !partners(left_period, right_period) && left_period.overlaps(right_period) {
+                overlaps.push(((*left_period).clone(), right_period.clone()));
+            }
+        }
+    }
+    for right_period in &differing_right {
+        for left_period in left {
+            if !partners(left_period, right_period)
+                && left_period.overlaps(right_period)
+                && !differing_left.contains(&left_period)
+            {
+                overlaps.push((left_period.clone(), (*right_period).clone()));
+            }
+        }
+    }
+    overlaps.sort_by_key(|(left, right)| left.start_time.min(right.start_time));
+    diff.overlaps = overlaps;
+    diff
+}
+
+/// Format a diff with one line per period, like a unified diff.
+///
+/// Lines for periods only in the left list start with `-`, periods only in the right list
+/// with `+`, and changed periods are shown as a `-` and `+` pair. Overlapping periods are
+/// listed last.
+pub fn render(diff: &Diff) -> String {
+    let mut lines: Vec<(DateTime<Utc>, String)> = Vec::new();
+    for period in &diff.only_left {
+        lines.push((
+            period.start_time,
+            format!("{} {}\n", "-".red(), period.summary()),
+        ));
+    }
+    for period in &diff.only_right {
+        lines.push((
+            period.start_time,
+            format!("{} {}\n", "+".green(), period.summary()),
+        ));
+    }
+    for (left, right) in &diff.changed {
+        lines.push((
+            left.start_time,
+            format!(
+                "{} {}\n{} {}\n",
+                "-".red(),
+                left.summary(),
+                "+".green(),
+                right.summary()
+            ),
+        ));
+    }
+    lines.sort_by_key(|(start_time, _)| *start_time);
+    let mut rendered: String = lines.into_iter().map(|(_, line)| line).collect();
+    if !diff.overlaps.is_empty() {
+        rendered.push_str(format!("{} overlapping periods:\n", diff.overlaps.len()).as_str());
+        for (left, right) in &diff.overlaps {
+            rendered.push_str(
+                format!(
+                    "  {} {}\n  {} {}\n",
+                    "-".red(),
+                    left.summary(),
+                    "+".green(),
+                    right.summary()
+                )
+                .as_str(),
+            );
+        }
+    }
+    rendered
+}
+
+/// Format a diff as JSON, with `left` and `right` objects for each changed or overlapping pair.
+pub fn to_json(diff: &Diff) -> Value {
+    let pairs = |pairs: &[(Period, Period)]| -> Vec<Value> {
+        pairs
+            .iter()
+            .map(|(left, right)| json!({ "left": left, "right": right }))
+            .collect()
+    };
+    json!({
+        "only_left": diff.only_left,
+        "only_right": diff.only_right,
+        "changed": pairs(&diff.changed),
+        "overlaps": pairs(&diff.overlaps),
+    })
+}
+
+/// Match up periods with the same start time from a `left` and a `right` list.
+///
+/// Periods with the same content are matched first, then periods with the same id. If one
+/// period is left on each side, they are matched too. Every period is in exactly one pair.
+pub(crate) fn pair<'a>(
+    left: &[&'a Period],
+    right: &[&'a Period],
+) -> Vec<(Option<&'a Period>, Option<&'a Period>)> {
+    let mut left: Vec<Option<&Period>> = left.iter().copied().map(Some).collect();
+    let mut right: Vec<Option<&Period>> = right.iter().copied().map(Some).collect();
+    let mut pairs = Vec::new();
+    let matchers: [fn(&Period, &Period) -> bool; 2] = [same, |a, b| a.id == b.id];
+    for matches in &matchers {
+        for left_slot in left.iter_mut() {
+            let left_period = match left_slot {
+                Some(period) => *period,
+                None => continue,
+            };
+            let found = right
+                .iter_mut()
+                .find(|right_slot| right_slot.is_some_and(|right| matches(left_period, right)));
+            if let Some(right_slot) = found {
+                pairs.push((left_slot.take(), right_slot.take()));
+            }
+        }
+    }
+    let left: Vec<&Period> = left.into_iter().flatten().collect();
+    let right: Vec<&Period> = right.into_iter().flatten().collect();
+    if left.len() == 1 && right.len() == 1 {
+        pairs.push((Some(left[0]), Some(right[0])));
+    } else {
+        pairs.extend(left.into_iter().map(|period| (Some(period), None)));
+        pairs.extend(right.into_iter().map(|period| (None, Some(period))));
+    }
+    pairs
+}
+
+/// Check if two periods with the same start time match, ignoring their ids
+pub(crate) fn same(a: &Period, b: &Period) -> bool {
+    a.project == b.project && a.end_time == b.end_time && a.tags == b.tags && a.note == b.note
+}
@@ -0,0 +1,80 @@
+//! Helpers for safely writing data files.
+//!
+//! Several `doug` processes can run at the same time, e.g. a status bar polling
+//! `doug status` while a project is stopped. Writes go through a temporary file that is
+//! renamed over the original, so readers never see a half written file, and commands hold
+//! an advisory lock on the data directory while they load, modify and save.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Name of the lock file in the data directory
+const LOCK_FILE: &str = "periods.lock";
+
+/// Take an exclusive lock on a data directory, waiting for other processes to release it.
+///
+/// The lock is released when the returned file is dropped.
+pub fn lock(folder: &Path) -> Result<File, String> {
+    let location = folder.join(LOCK_FILE);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&location)
+        .map_err(|err| format!("Couldn't open lock file {:?}: {:?}", location, err))?;
+    file.lock()
+        .map_err(|err| format!("Couldn't lock data directory: {:?}", err))?;
+    Ok(file)
+}
+
+/// Replace the contents of a file without leaving it partially written.
+///
+/// The data is written to a temporary file next to `location`, synced to disk, and then
+/// renamed over `location`.
+pub fn write_atomic(location: &Path, contents: &[u8]) -> Result<(), String> {
+    let mut temp_location = location.as_os_str().to_owned();
+    temp_location.push(".tmp");
+    let temp_location = Path::new(&temp_location);
+
+    let mut temp_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(temp_location)
+        .map_err(|err| format!("Couldn't open file for saving: {:?}", err))?;
+    temp_file
+        .write_all(contents)
+        .and_then(|_| temp_file.sync_all())
+        .map_err(|_| "Couldn't write serialized data to file".to_string())?;
+    fs::rename(temp_location, location)
+        .map_err(|err| format!("Couldn't replace {:?}: {:?}", location, err))?;
+    Ok(())
+}
+
+/// Overwrite a file, or every file in a directory, with zeros and remove it.
+///
+/// Used for unencrypted copies of periods. The overwrite is best effort: file systems that
+/// copy on write or keep snapshots may still hold the old contents.
+pub fn remove_securely(location: &Path) -> Result<(), String> {
+    if location.is_dir() {
+        let entries = fs::read_dir(location)
+            .map_err(|err| format!("Couldn't read {:?}: {:?}", location, err))?;
+        for entry in entries.filter_map(Result::ok) {
+            remove_securely(&entry.path())?;
+        }
+        return fs::remove_dir(location)
+            .map_err(|err| format!("Couldn't remove {:?}: {:?}", location, err));
+    }
+    let length = fs::metadata(location)
+        .map_err(|err| format!("Couldn't read {:?}: {:?}", location, err))?
+        .len();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(location)
+        .map_err(|err| format!("Couldn't open {:?}: {:?}", location, err))?;
+    file.write_all(&vec![0; length as usize])
+        .and_then(|_| file.sync_all())
+        .map_err(|err| format!("Couldn't overwrite {:?}: {:?}", location, err))?;
+    fs::remove_file(location).map_err(|err| format!("Couldn't remove {:?}: {:?}", location, err))
+}
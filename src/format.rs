@@ -4,6 +4,10 @@ pub fn datetime(time: DateTime<Utc>) -> String {
     time.with_timezone(&Local).format("%F %H:%M").to_string()
 }
 
+pub fn date(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%F").to_string()
+}
+
 pub fn time(time: DateTime<Utc>) -> String {
     time.with_timezone(&Local).format("%H:%M").to_string()
 }
@@ -30,3 +34,42 @@ pub fn duration(duration: Duration) -> String {
         )
     }
 }
+
+/// Parse a duration written like [duration] formats it, e.g. `1h30m`, `45m` or `1h 30m 15s`.
+///
+/// # Examples
+/// ```
+/// # extern crate chrono;
+/// # extern crate doug;
+/// # use chrono::Duration;
+/// use doug::format::parse_duration;
+///
+/// assert_eq!(parse_duration("1h30m"), Ok(Duration::minutes(90)));
+/// assert!(parse_duration("90").is_err());
+/// ```
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let error = || format!("Couldn't parse duration {}", text);
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    let mut found_unit = false;
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: i64 = number.parse().map_err(|_| error())?;
+        total = total
+            + match c {
+                'h' => Duration::hours(value),
+                'm' => Duration::minutes(value),
+                's' => Duration::seconds(value),
+                _ => return Err(error()),
+            };
+        number.clear();
+        found_unit = true;
+    }
+    if !number.is_empty() || !found_unit {
+        return Err(error());
+    }
+    Ok(total)
+}
@@ -0,0 +1,226 @@
+//! History and sync of the data directory with git.
+//!
+//! When [settings::Settings::git] is set, the data directory is a git repository and every save
+//! commits the data file. Only data files are tracked; settings, backups and the undo history
+//! stay local to each machine. `git` has to be installed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use crate::file;
+
+/// Ignore everything in the data directory except data files
+const GITIGNORE: &str = "*\n!.gitignore\n!periods/\n!periods/*.json\n!periods.json\n!periods.sqlite\n!periods.journal\n!periods.enc\n";
+
+/// Branch that periods are committed to in new repositories
+const BRANCH: &str = "main";
+
+/// Run git in `folder`, returning its output.
+fn run(folder: &Path, args: &[&str]) -> Result<Output, String> {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(folder);
+    // commits shouldn't fail on machines without a configured identity
+    if !has_identity(folder) {
+        command.args(["-c", "user.name=doug", "-c", "user.email=doug@localhost"]);
+    }
+    command
+        .args(args)
+        .output()
+        .map_err(|err| format!("Couldn't run git: {:?}", err))
+}
+
+/// Run git in `folder`, failing if git exits with an error.
+fn check(folder: &Path, args: &[&str]) -> Result<String, String> {
+    let output = run(folder, args)?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn has_identity(folder: &Path) -> bool {
+    Command::new("git")
+        .arg("-C")
+        .arg(folder)
+        .args(["config", "user.email"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Make `folder` a git repository if it isn't one yet.
+pub fn init(folder: &Path) -> Result<(), String> {
+    if folder.join(".git").exists() {
+        return Ok(());
+    }
+    check(folder, &["init", "--quiet"])?;
+    check(
+        folder,
+        &["symbolic-ref", "HEAD", &format!("refs/heads/{}", BRANCH)],
+    )?;
+    file::write_atomic(&folder.join(".gitignore"), GITIGNORE.as_bytes())?;
+    commit(
+        folder,
+        &[Path::new(".gitignore")],
+        "Track periods with doug",
+    )
+}
+
+/// Commit changes to `files`, which are relative to `folder`. Files or directories that
+/// don't exist and were never committed are skipped.
+///
+/// Nothing is committed if the files didn't change, unless a merge is in progress.
+pub fn commit(folder: &Path, files: &[&Path], message: &str) -> Result<(), String> {
+    let mut files_to_add = Vec::new();
+    for file in files {
+        let file = file.to_string_lossy().into_owned();
+        if folder.join(&file).exists() || !check(folder, &["ls-files", "--", &file])?.is_empty() {
+            files_to_add.push(file);
+        }
+    }
+    // without paths, git would add the whole directory
+    if !files_to_add.is_empty() {
+        let mut add = vec!["add", "--all", "--force", "--"];
+        add.extend(files_to_add.iter().map(String::as_str));
+        check(folder, &add)?;
+    }
+    let unchanged = run(folder, &["diff", "--cached", "--quiet"])?
+        .status
+        .success();
+    if unchanged && !folder.join(".git").join("MERGE_HEAD").exists() {
+        return Ok(());
+    }
+    check(folder, &["commit", "--quiet", "--message", message])?;
+    Ok(())
+}
+
+/// Name of the checked out branch
+pub fn branch(folder: &Path) -> Result<String, String> {
+    Ok(check(folder, &["symbolic-ref", "--short", "HEAD"])?
+        .trim()
+        .to_string())
+}
+
+/// How the local history relates to the remote one after [fetch]
+#[derive(Debug, PartialEq, Eq)]
+pub enum State {
+    /// The remote has nothing the local history doesn't have
+    Ahead,
+    /// The local history can be fast-forwarded to the remote one
+    Behind,
+    /// Both sides have commits the other doesn't
+    Diverged,
+}
+
+/// Fetch `branch` from `remote`. Returns `None` if the remote doesn't have the branch yet.
+pub fn fetch(folder: &Path, remote: &str, branch: &str) -> Result<Option<State>, String> {
+    let exists = run(
+        folder,
+        &["ls-remote", "--exit-code", "--heads", remote, branch],
+    )?;
+    match exists.status.code() {
+        Some(0) => {}
+        // the branch is missing
+        Some(2) => return Ok(None),
+        _ => {
+            return Err(format!(
+                "Couldn't reach remote {}: {}",
+                remote,
+                String::from_utf8_lossy(&exists.stderr).trim()
+            ))
+        }
+    }
+    check(folder, &["fetch", "--quiet", remote, branch])?;
+    let is_ancestor = |ancestor: &str, descendant: &str| -> Result<bool, String> {
+        Ok(run(
+            folder,
+            &["merge-base", "--is-ancestor", ancestor, descendant],
+        )?
+        .status
+        .success())
+    };
+    Ok(Some(if is_ancestor("FETCH_HEAD", "HEAD")? {
+        State::Ahead
+    } else if is_ancestor("HEAD", "FETCH_HEAD")? {
+        State::Behind
+    } else {
+        State::Diverged
+    }))
+}
+
+/// Move the local history forward to the fetched one.
+pub fn fast_forward(folder: &Path) -> Result<(), String> {
+    check(folder, &["merge", "--quiet", "--ff-only", "FETCH_HEAD"])?;
+    Ok(())
+}
+
+/// Start merging the fetched history, keeping the local files.
+///
+/// The merge is finished by the next [commit].
+pub fn start_merge(folder: &Path) -> Result<(), String> {
+    check(
+        folder,
+        &[
+            "merge",
+            "--quiet",
+            "--no-commit",
+            "--allow-unrelated-histories",
+            "--strategy",
+            "ours",
+            "FETCH_HEAD",
+        ],
+    )?;
+    Ok(())
+}
+
+/// Files at `path` in the fetched history, which is either a file or a directory.
+pub fn fetched_files(folder: &Path, path: &Path) -> Result<Vec<PathBuf>, String> {
+    Ok(check(
+        folder,
+        &[
+            "ls-tree",
+            "-r",
+            "--name-only",
+            "FETCH_HEAD",
+            "--",
+            &path.to_string_lossy(),
+        ],
+    )?
+    .lines()
+    .map(PathBuf::from)
+    .collect())
+}
+
+/// Copy a file from the fetched history to `destination`.
+///
+/// Returns `false` if the fetched history doesn't have the file.
+pub fn show_fetched(folder: &Path, file: &Path, destination: &Path) -> Result<bool, String> {
+    let output = run(
+        folder,
+        &["show", &format!("FETCH_HEAD:{}", file.to_string_lossy())],
+    )?;
+    if !output.status.success() {
+        return Ok(false);
+    }
+    fs::write(destination, output.stdout)
+        .map_err(|err| format!("Couldn't write {:?}: {:?}", destination, err))?;
+    Ok(true)
+}
+
+/// Push the checked out branch to `branch` on `remote`.
+pub fn push(folder: &Path, remote: &str, branch: &str) -> Result<(), String> {
+    check(
+        folder,
+        &[
+            "push",
+            "--quiet",
+            remote,
+            &format!("HEAD:refs/heads/{}", branch),
+        ],
+    )?;
+    Ok(())
+}
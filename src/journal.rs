@@ -0,0 +1,359 @@
+//! Append-only journal of changes to periods.
+//!
+//! With journal storage, every save appends the changes since the last save to
+//! `periods.journal` as events, one JSON object per line. Periods are rebuilt by replaying
+//! the events in order, so the journal doubles as an audit trail of every change.
+//!
+//! ```text
+//! {"at":"2026-10-16T08:00:00Z","event":"started","period":{"id":"ae4e168",...}}
+//! {"at":"2026-10-16T09:30:00Z","event":"stopped","id":"ae4e168","end_time":"2026-10-16T09:30:00Z"}
+//! ```
+//!
+//! The journal only grows. [Storage::compact] replaces it with a single `added` event per
+//! period.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Storage;
+use crate::{file, Period};
+
+/// A change to a single period
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event {
+    /// A running period was created
+    Started { period: Period },
+    /// A running period got an end time
+    Stopped { id: String, end_time: DateTime<Utc> },
+    /// A period was moved to another project
+    Amended { id: String, project: String },
+    /// Any other change to a period
+    Edited { period: Period },
+    /// A finished period was created
+    Added { period: Period },
+    /// A period was removed
+    Deleted { id: String },
+    /// A period was created or changed by merging another data file
+    Merged { source: String, period: Period },
+}
+
+/// An event with the time it was recorded
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// Find the events that turn `old` into `new`.
+///
+/// Periods are matched by id. Changed and new periods from a merge are recorded as
+/// [Event::Merged] when `merge_source` is set.
+///
+/// # Examples
+/// ```
+/// # extern crate doug;
+/// # extern crate serde_json;
+/// # extern crate tempfile;
+/// use doug::journal::{Event, JournalStorage};
+/// use doug::storage::Storage;
+/// use doug::Period;
+///
+/// let period = |id: &str, project: &str, end_time: Option<&str>| -> Period {
+///     serde_json::from_value(serde_json::json!({
+///         "id": id,
+///         "project": project,
+///         "start_time": "2026-10-16T09:00:00Z",
+///         "end_time": end_time,
+///     }))
+///     .unwrap()
+/// };
+/// let saves = vec![
+///     vec![period("a", "doug", None)],
+///     vec![period("a", "doug", Some("2026-10-16T10:00:00Z"))],
+///     vec![
+///         period("a", "email", Some("2026-10-16T10:00:00Z")),
+///         period("b", "doug", Some("2026-10-16T11:00:00Z")),
+///     ],
+///     vec![period("b", "doug", Some("2026-10-16T11:00:00Z"))],
+/// ];
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let location = dir.path().join("periods.journal");
+/// let mut journal = JournalStorage::new(location.clone());
+/// for periods in &saves {
+///     journal.save(periods).unwrap();
+///     assert_eq!(&JournalStorage::new(location.clone()).load().unwrap(), periods);
+/// }
+/// let kinds: Vec<&str> = journal.entries().unwrap().iter().map(|entry| match entry.event {
+///     Event::Started { .. } => "started",
+///     Event::Stopped { .. } => "stopped",
+///     Event::Amended { .. } => "amended",
+///     Event::Added { .. } => "added",
+///     Event::Deleted { .. } => "deleted",
+///     _ => "other",
+/// }).collect();
+/// assert_eq!(kinds, ["started", "stopped", "amended", "added", "deleted"]);
+///
+/// // compacting leaves one `added` event per period and replays to the same periods
+/// let latest = saves.last().unwrap();
+/// assert_eq!(journal.compact(latest).unwrap(), 4);
+/// assert_eq!(&JournalStorage::new(location).load().unwrap(), latest);
+/// ```
+pub fn events(
+    old: &HashMap<String, Period>,
+    new: &[Period],
+    merge_source: Option<&str>,
+) -> Vec<Event> {
+    let mut events: Vec<Event> = Vec::new();
+    let new_ids: HashSet<&str> = new.iter().map(|period| period.id.as_str()).collect();
+    let mut ids: Vec<&String> = old
+        .keys()
+        .filter(|id| !new_ids.contains(id.as_str()))
+        .collect();
+    ids.sort();
+    for id in ids {
+        events.push(Event::Deleted { id: id.clone() });
+    }
+    for period in new {
+        let previous = old.get(&period.id);
+        if previous == Some(period) {
+            continue;
+        }
+        if let Some(source) = merge_source {
+            events.push(Event::Merged {
+                source: source.to_string(),
+                period: period.clone(),
+            });
+            continue;
+        }
+        events.push(match previous {
+            None if period.end_time.is_none() => Event::Started {
+                period: period.clone(),
+            },
+            None => Event::Added {
+                period: period.clone(),
+            },
+            Some(previous) => {
+                let stopped = Period {
+                    end_time: period.end_time,
+                    ..previous.clone()
+                };
+                let amended = Period {
+                    project: period.project.clone(),
+                    ..previous.clone()
+                };
+                match period.end_time {
+                    Some(end_time) if previous.end_time.is_none() && &stopped == period => {
+                        Event::Stopped {
+                            id: period.id.clone(),
+                            end_time,
+                        }
+                    }
+                    _ if &amended == period => Event::Amended {
+                        id: period.id.clone(),
+                        project: period.project.clone(),
+                    },
+                    _ => Event::Edited {
+                        period: period.clone(),
+                    },
+                }
+            }
+        });
+    }
+    events
+}
+
+/// Rebuild periods by applying events in order.
+///
+/// Returns an error naming the first event that refers to a period that doesn't exist.
+///
+/// # Examples
+/// ```
+/// # extern crate doug;
+/// # extern crate serde_json;
+/// use doug::journal::{replay, Entry};
+///
+/// let entries: Vec<Entry> = serde_json::from_value(serde_json::json!([
+///     {"at": "2026-10-16T09:00:00Z", "event": "deleted", "id": "ae4e168"},
+/// ]))
+/// .unwrap();
+/// assert!(replay(&entries).is_err());
+/// ```
+pub fn replay<'a, I: IntoIterator<Item = &'a Entry>>(entries: I) -> Result<Vec<Period>, String> {
+    let mut periods: HashMap<String, Period> = HashMap::new();
+    for entry in entries {
+        match &entry.event {
+            Event::Started { period }
+            | Event::Edited { period }
+            | Event::Added { period }
+            | Event::Merged { period, .. } => {
+                periods.insert(period.id.clone(), period.clone());
+            }
+            Event::Stopped { id, end_time } => {
+                find(&mut periods, id, entry)?.end_time = Some(*end_time);
+            }
+            Event::Amended { id, project } => {
+                find(&mut periods, id, entry)?.project = project.clone();
+            }
+            Event::Deleted { id } => {
+                periods
+                    .remove(id)
+                    .ok_or_else(|| missing_period(id, entry))?;
+            }
+        }
+    }
+    let mut periods: Vec<Period> = periods.into_values().collect();
+    periods.sort_by(|a, b| a.start_time.cmp(&b.start_time).then(a.id.cmp(&b.id)));
+    Ok(periods)
+}
+
+fn find<'a>(
+    periods: &'a mut HashMap<String, Period>,
+    id: &str,
+    entry: &Entry,
+) -> Result<&'a mut Period, String> {
+    periods.get_mut(id).ok_or_else(|| missing_period(id, entry))
+}
+
+fn missing_period(id: &str, entry: &Entry) -> String {
+    format!(
+        "Journal event from {} refers to unknown period {}",
+        entry.at.to_rfc3339(),
+        id
+    )
+}
+
+/// Periods stored as an append-only journal of events.
+pub struct JournalStorage {
+    location: PathBuf,
+    /// Periods as of the last event in the journal, by id
+    stored: HashMap<String, Period>,
+    /// Source of the merge that the next save records
+    merge_source: Option<String>,
+}
+
+impl JournalStorage {
+    pub fn new(location: PathBuf) -> Self {
+        JournalStorage {
+            location,
+            stored: HashMap::new(),
+            merge_source: None,
+        }
+    }
+
+    /// Read all entries, in the order they were recorded.
+    pub fn entries(&self) -> Result<Vec<Entry>, String> {
+        let data_file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&self.location)
+            .map_err(|_| format!("Couldn't open journal: {:?}\n", self.location))?;
+        let mut entries = Vec::new();
+        for (number, line) in BufReader::new(data_file).lines().enumerate() {
+            let line = line.map_err(|err| format!("Couldn't read journal: {:?}", err))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line).map_err(|err| {
+                format!(
+                    "There was a serialization issue on line {} of the journal: {:?}\n",
+                    number + 1,
+                    err
+                )
+            })?;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+impl Storage for JournalStorage {
+    fn load(&mut self) -> Result<Vec<Period>, String> {
+        let periods = replay(&self.entries()?)?;
+        self.stored = by_id(&periods);
+        Ok(periods)
+    }
+
+    /// Append the changes since the last load or save.
+    fn save(&mut self, periods: &[Period]) -> Result<(), String> {
+        let at = Utc::now();
+        let entries: Vec<Entry> = events(&self.stored, periods, self.merge_source.as_deref())
+            .into_iter()
+            .map(|event| Entry { at, event })
+            .collect();
+        if !entries.is_empty() {
+            let mut journal = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.location)
+                .map_err(|err| format!("Couldn't open journal for saving: {:?}", err))?;
+            journal
+                .write_all(serialize(&entries)?.as_bytes())
+                .and_then(|_| journal.sync_data())
+                .map_err(|_| "Couldn't write events to journal".to_string())?;
+        }
+        self.stored = by_id(periods);
+        self.merge_source = None;
+        Ok(())
+    }
+
+    fn location(&self) -> &Path {
+        &self.location
+    }
+
+    /// Saving only appends to the journal, so nothing needs a backup.
+    fn changed_files(&self, _periods: &[Period]) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    fn merging(&mut self, source: &Path) {
+        self.merge_source = Some(source.to_string_lossy().into_owned());
+    }
+
+    /// Replace the journal with one `added` event per period, returning the number of events
+    /// that were removed.
+    fn compact(&mut self, periods: &[Period]) -> Result<usize, String> {
+        let before = self.entries()?.len();
+        let at = Utc::now();
+        let entries: Vec<Entry> = periods
+            .iter()
+            .map(|period| Entry {
+                at,
+                event: Event::Added {
+                    period: period.clone(),
+                },
+            })
+            .collect();
+        file::write_atomic(&self.location, serialize(&entries)?.as_bytes())?;
+        self.stored = by_id(periods);
+        Ok(before.saturating_sub(entries.len()))
+    }
+}
+
+fn by_id(periods: &[Period]) -> HashMap<String, Period> {
+    periods
+        .iter()
+        .map(|period| (period.id.clone(), period.clone()))
+        .collect()
+}
+
+fn serialize(entries: &[Entry]) -> Result<String, String> {
+    let mut serialized = String::new();
+    for entry in entries {
+        serialized.push_str(
+            &serde_json::to_string(entry)
+                .map_err(|_| "Couldn't serialize data to string".to_string())?,
+        );
+        serialized.push('\n');
+    }
+    Ok(serialized)
+}
@@ -1,15 +1,31 @@
 #![allow(clippy::new_ret_no_self)]
 
+pub mod backup;
+pub mod billing;
+pub mod calendar;
+pub mod check;
+pub mod crypto;
+pub mod diff;
+pub mod file;
 pub mod format;
+pub mod git;
+pub mod journal;
+pub mod merge;
+pub mod project;
+pub mod report;
+pub mod schema;
 pub mod settings;
+pub mod state;
+pub mod storage;
+pub mod undo;
 
 use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt;
 use std::fs;
 use std::fs::{DirBuilder, OpenOptions};
-use std::io::Write;
+use std::io::{self, BufRead};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
@@ -17,25 +33,319 @@ use chrono::{Date, DateTime, Duration, Local, NaiveDate, TimeZone, Utc};
 use chrono_english::{parse_date_string, Dialect};
 use colored::*;
 use serde::{Deserialize, Serialize};
-use serde_json::Error;
 
 type ProjectName = String;
 
 #[derive(Eq, PartialEq, Serialize, Deserialize, Debug, Clone)]
 pub struct Period {
+    /// Short identifier, stable across saves. Generated on load for older data files.
+    #[serde(default)]
+    id: String,
     project: ProjectName,
     start_time: DateTime<Utc>,
     end_time: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
 }
 
 impl Period {
-    fn new(project: &str) -> Period {
-        Period {
+    fn new(project: &str, tags: &[String]) -> Period {
+        let mut period = Period {
+            id: String::new(),
             project: String::from(project),
             start_time: Utc::now(),
             end_time: None,
+            tags: tags.to_vec(),
+            note: None,
+        };
+        period.id = period.generate_id(0);
+        period
+    }
+
+    /// Derive a short id from the start time and project name.
+    ///
+    /// This uses FNV-1a so older data files get the same ids every time they are loaded.
+    /// `salt` is used to pick a different id when there is a collision.
+    fn generate_id(&self, salt: u32) -> String {
+        let seed = format!("{}{}{}", self.start_time.to_rfc3339(), self.project, salt);
+        let hash = seed.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        format!("{:016x}", hash)[..ID_LENGTH].to_string()
+    }
+
+    /// Attach a note to the period. Notes added later are appended on a new line.
+    fn add_note(&mut self, note: &str) {
+        self.note = Some(match self.note.take() {
+            Some(existing) => format!("{}\n{}", existing, note),
+            None => note.to_string(),
+        });
+    }
+
+    /// One line description with id, date, times and project
+    fn summary(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            self.id.dimmed(),
+            format::date(self.start_time),
+            self,
+            self.project.blue()
+        )
+    }
+
+    /// End of the period, or now if the period is still running
+    fn end_or_now(&self) -> DateTime<Utc> {
+        self.end_time.unwrap_or_else(Utc::now)
+    }
+
+    /// The part of the period between `from` and `to`, or `None` if it has no time between
+    /// them. A running period stays running unless it's cut at `to`.
+    fn clip(&self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Option<Period> {
+        let end_time = self.end_or_now();
+        if from.is_some_and(|from| end_time <= from) || to.is_some_and(|to| self.start_time >= to) {
+            return None;
+        }
+        let mut clipped = self.clone();
+        if let Some(from) = from {
+            clipped.start_time = max(self.start_time, from);
+        }
+        if let Some(to) = to.filter(|to| end_time > *to) {
+            clipped.end_time = Some(to);
+        }
+        Some(clipped)
+    }
+
+    /// Split the period at every local midnight it runs past, e.g. a period from 23:00 to
+    /// 01:00 becomes one from 23:00 to 00:00 and one from 00:00 to 01:00.
+    fn split_days(&self) -> Vec<Period> {
+        let mut parts = Vec::new();
+        let mut part = self.clone();
+        loop {
+            let midnight = (part.start_time.with_timezone(&Local).date() + Duration::days(1))
+                .and_hms(0, 0, 0)
+                .with_timezone(&Utc);
+            if part.end_or_now() <= midnight {
+                parts.push(part);
+                return parts;
+            }
+            let mut rest = part.clone();
+            rest.start_time = midnight;
+            part.end_time = Some(midnight);
+            parts.push(part);
+            part = rest;
+        }
+    }
+
+    /// Check if two periods share any time
+    fn overlaps(&self, other: &Period) -> bool {
+        self.start_time < other.end_or_now() && other.start_time < self.end_or_now()
+    }
+
+    fn has_tags(&self, tags: &[&str]) -> bool {
+        tags.iter().all(|tag| self.tags.iter().any(|t| t == tag))
+    }
+
+    /// Tags formatted the way they are entered, e.g. `+review +billable`
+    fn tag_list(&self) -> String {
+        self.tags
+            .iter()
+            .map(|tag| format!("+{}", tag))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+/// Parse a humanized date (e.g. `thursday 9:00am`, `today 12:15pm`) in local time.
+///
+/// `chrono_english` doesn't understand `today`, so it is replaced with the current date.
+fn parse_date(date: &str) -> Result<DateTime<Local>, String> {
+    let now = Local::now();
+    let trimmed = date.trim();
+    let expanded = match trimmed.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("today") => {
+            format!("{}{}", now.format("%Y-%m-%d"), &trimmed[5..])
+        }
+        _ => trimmed.to_string(),
+    };
+    parse_date_string(&expanded, now, Dialect::Us)
+        .map_err(|_| format!("Couldn't parse date {}", date))
+}
+
+/// Number of hex characters in a period id
+const ID_LENGTH: usize = 7;
+
+/// Give every period without an id (or with a duplicate id) a unique one.
+fn assign_ids(periods: &mut [Period]) {
+    let mut seen = HashSet::new();
+    for period in periods.iter_mut() {
+        let mut salt = 0;
+        while period.id.is_empty() || seen.contains(&period.id) {
+            period.id = period.generate_id(salt);
+            salt += 1;
+        }
+        seen.insert(period.id.clone());
+    }
+}
+
+/// Parse tag arguments of the form `+tag`.
+///
+/// The leading `+` is stripped and duplicate tags are removed.
+fn parse_tags(args: &[&str]) -> Result<Vec<String>, String> {
+    let mut tags: Vec<String> = Vec::new();
+    for arg in args {
+        match arg.strip_prefix('+') {
+            Some(tag) if !tag.is_empty() => {
+                if !tags.iter().any(|t| t == tag) {
+                    tags.push(tag.to_string());
+                }
+            }
+            _ => return Err(format!("Invalid tag {}. Tags must start with '+'.", arg)),
+        }
+    }
+    Ok(tags)
+}
+
+/// Sum the time of periods that started between `from` and `to` into the column holding
+/// their start date. `columns` are the starts of the columns, in order.
+fn buckets<'a>(
+    periods: impl Iterator<Item = &'a Period>,
+    columns: &[Date<Local>],
+    from: Date<Local>,
+    to: Date<Local>,
+) -> Vec<Duration> {
+    let mut buckets = vec![Duration::zero(); columns.len()];
+    for period in periods {
+        let start_date = period.start_time.with_timezone(&Local).date();
+        let column = columns.partition_point(|start| *start <= start_date);
+        if start_date < from || to < start_date || column == 0 {
+            continue;
         }
+        buckets[column - 1] = buckets[column - 1]
+            + period
+                .end_time
+                .unwrap_or_else(Utc::now)
+                .signed_duration_since(period.start_time);
     }
+    buckets
+}
+
+/// How [Doug::report] aggregates periods.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum GroupBy {
+    #[default]
+    Project,
+    Tag,
+}
+
+impl std::str::FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "project" => Ok(GroupBy::Project),
+            "tag" => Ok(GroupBy::Tag),
+            _ => Err(format!("Invalid group {}", s)),
+        }
+    }
+}
+
+/// Options for [Doug::add].
+///
+/// A period needs either both `from` and `to`, or a `duration`. With a duration, the period
+/// starts at `from` or ends at `to`. If neither is given, it is placed on the day `on` (default:
+/// today), right after the last period of that day or at 9:00 if the day is empty.
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions<'a> {
+    /// humanized start date (e.g. `today 9:00`)
+    pub from: Option<&'a str>,
+    /// humanized end date (e.g. `today 10:30`)
+    pub to: Option<&'a str>,
+    /// length of the period (e.g. `1h30m`)
+    pub duration: Option<&'a str>,
+    /// humanized day of the period (e.g. `yesterday`)
+    pub on: Option<&'a str>,
+    pub note: Option<&'a str>,
+    /// add the period even if it overlaps existing periods
+    pub force: bool,
+}
+
+/// Changes to settings for [Doug::settings]. Settings that are `None` are left alone.
+#[derive(Debug, Clone, Default)]
+pub struct SettingsOptions<'a> {
+    /// new location for the data file
+    pub path: Option<&'a str>,
+    /// reset settings to their defaults
+    pub clear: bool,
+    /// number of recent backups to keep
+    pub keep_last: Option<usize>,
+    /// number of days to keep a daily backup for
+    pub keep_daily: Option<usize>,
+    /// commit the data file to a git repository in the data directory on every save
+    pub git: Option<bool>,
+    /// git remote to sync with, see [Doug::sync]
+    pub remote: Option<&'a str>,
+    /// file holding the passphrase for encrypted storage
+    pub key_file: Option<&'a str>,
+    /// keep a json file per year or per month, see [storage::ShardedStorage]
+    pub shard_by: Option<storage::ShardSize>,
+    /// day that weeks start on in reports
+    pub first_weekday: Option<chrono::Weekday>,
+    /// hourly rates to set for projects, or for tags with their `+` (e.g. `+overtime`)
+    pub rates: Vec<(&'a str, billing::Money)>,
+    /// projects or `+tags` to remove the rate of
+    pub remove_rates: Vec<&'a str>,
+    /// currency of the rates (e.g. `EUR`)
+    pub currency: Option<&'a str>,
+}
+
+/// Options for [Doug::log].
+#[derive(Debug, Clone, Default)]
+pub struct LogOptions<'a> {
+    /// only list periods with a note containing this pattern (case insensitive)
+    pub grep: Option<&'a str>,
+    /// humanized first day to list (e.g. `2018-1-1`)
+    pub from_date: Option<&'a str>,
+    /// humanized last day to list (e.g. `2018-1-20`)
+    pub to_date: Option<&'a str>,
+    /// list periods running past midnight under each day they cover
+    pub split_overnight: bool,
+}
+
+/// Options for [Doug::report].
+///
+/// A `named` range takes precedence over the `past_*` counts, which take precedence over
+/// `from_date` and `to_date`. Past years and months are calendar years and months, e.g. one
+/// past month on 16 October starts on 16 September.
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions<'a> {
+    /// calendar range of the report (e.g. last month), with weeks starting on
+    /// [settings::Settings::first_weekday]
+    pub named: Option<calendar::NamedRange>,
+    pub past_years: i32,
+    pub past_months: i32,
+    pub past_weeks: i32,
+    pub past_days: i32,
+    /// humanized start date of the report (e.g. `2018-1-1`)
+    pub from_date: Option<&'a str>,
+    /// humanized end date of the report (e.g. `2018-1-20`)
+    pub to_date: Option<&'a str>,
+    /// only include periods that have all of these tags
+    pub tags: Vec<&'a str>,
+    /// only include projects matching one of these patterns (e.g. `client/*`)
+    pub projects: Vec<&'a str>,
+    pub group_by: GroupBy,
+    /// number of project levels to show. Deeper projects are rolled up into their parents.
+    pub depth: Option<usize>,
+    pub format: report::Format,
+    /// split the time of every row into days, weeks or months
+    pub by: Option<report::Bucket>,
+    /// count the time of periods running past midnight on each day they cover, instead of
+    /// the day they started
+    pub split_overnight: bool,
+    /// show hours, rate and amount per project instead, see [billing]
+    pub billing: bool,
 }
 
 impl fmt::Display for Period {
@@ -62,12 +372,21 @@ impl fmt::Display for Period {
 /// This is the backend where all the logic for Doug is kept.
 /// The current implementation uses `$HOME/.doug/` for storing data,
 /// while the CLI stuff is handled by [clap] in `main.rs`.
-#[derive(Clone)]
 pub struct Doug {
     periods: Vec<Period>,
     /// Path to settings.json file
     settings: settings::Settings,
     settings_location: PathBuf,
+    /// Where periods are saved. Missing for periods loaded from another file.
+    storage: Option<Box<dyn storage::Storage>>,
+    /// Lock on the data directory, held until Doug is dropped
+    lock: Option<fs::File>,
+    /// Periods as they were last loaded or saved, to record undo history
+    saved: Vec<Period>,
+    /// Whether all periods are loaded, rather than only the shards commands needed so far
+    complete: bool,
+    /// Whether the period that started last is loaded
+    latest: bool,
 }
 
 type DougResult = Result<Option<String>, String>;
@@ -75,7 +394,8 @@ type DougResult = Result<Option<String>, String>;
 impl Doug {
     /// Initialize a new Doug instance
     ///
-    /// If missing, the data file will be created at `$HOME/.doug/periods.json`.
+    /// If missing, the data directory will be created at `$HOME/.doug/periods/`. Periods
+    /// aren't loaded until a command needs them, see [Doug::status].
     ///
     /// # Arguments
     /// * `path` — an optional path to the root of the data folder.
@@ -112,38 +432,128 @@ impl Doug {
             .create(&settings.data_location)
             .map_err(|_| format!("Couldn't create data directory: {:?}\n", folder))?;
 
-        // create data file
-        let location = settings.data_location.as_path().join("periods.json");
-        let data_file = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(&location)
-            .map_err(|_| format!("Couldn't open datafile: {:?}\n", location))?;
-        Doug::load_periods_from_file(&data_file, settings, folder)
+        // other doug processes wait here until we're done
+        let lock = file::lock(&settings.data_location)?;
+
+        // open data file, periods are loaded by the commands that need them
+        let storage = storage::open(&settings)?;
+        let mut doug = Doug::from_periods(Vec::new(), settings, folder);
+        doug.storage = Some(storage);
+        doug.lock = Some(lock);
+        doug.complete = false;
+        doug.latest = false;
+        Ok(doug)
+    }
+
+    /// Load the periods needed for `range` that aren't loaded yet.
+    fn require(&mut self, range: &storage::Range) -> Result<(), String> {
+        if self.complete {
+            return Ok(());
+        }
+        let storage = self
+            .storage
+            .as_mut()
+            .ok_or_else(|| "Periods weren't loaded from a data directory".to_string())?;
+        let (mut periods, complete) = match storage.load_range(range)? {
+            Some(periods) => (periods, *range == storage::Range::All),
+            None => (storage.load()?, true),
+        };
+        assign_ids(&mut periods);
+        self.saved.extend(periods.iter().cloned());
+        self.saved.sort_by_key(|period| period.start_time);
+        self.periods.extend(periods);
+        self.periods.sort_by_key(|period| period.start_time);
+        self.complete = complete;
+        self.latest |= complete || *range == storage::Range::Latest;
+        Ok(())
     }
 
+    /// Load the period that started last, and the periods in its shard.
+    ///
+    /// The shard is found with [state::State] if it's up to date, and by looking back from
+    /// the current shard otherwise. SQLite storage loads just the last period, while journal
+    /// and encrypted storage load all periods.
+    fn require_latest(&mut self) -> Result<(), String> {
+        if self.latest {
+            return Ok(());
+        }
+        match self.state().and_then(|state| state.last) {
+            Some(last) => {
+                let start = Some(last.start_time);
+                self.require(&storage::Range::Between(start, start))?;
+                self.latest = true;
+            }
+            None => {
+                self.require(&storage::Range::Latest)?;
+                self.save_state()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The period that started last, without loading periods if [state::State] is up to date.
+    fn last_period(&mut self) -> Result<Option<Period>, String> {
+        if !self.latest {
+            if let Some(state) = self.state() {
+                return Ok(state.last);
+            }
+        }
+        self.require_latest()?;
+        Ok(self.periods.last().cloned())
+    }
+
+    /// The state sidecar of the data directory, if it's up to date
+    fn state(&self) -> Option<state::State> {
+        if self.settings.storage == storage::StorageKind::Encrypted {
+            return None;
+        }
+        state::State::load(&self.settings.data_location)
+    }
+
+    /// Record the last period in the state sidecar, or remove the sidecar if the last period
+    /// isn't known.
+    fn save_state(&self) -> Result<(), String> {
+        let folder = &self.settings.data_location;
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Ok(()),
+        };
+        if !self.latest || self.settings.storage == storage::StorageKind::Encrypted {
+            return state::State::remove(folder);
+        }
+        let last = self.periods.last();
+        state::State::new(last.cloned(), &storage.state_files(last)).save(folder)
+    }
+
+    /// Load periods from a data file, migrating older file layouts in memory.
     pub fn load_periods_from_file(
         data_file: &std::fs::File,
         settings: settings::Settings,
         settings_location: std::path::PathBuf,
     ) -> Result<Self, String> {
-        // serialize periods from data file
-        let periods: Result<Vec<Period>, Error> = serde_json::from_reader(data_file);
-
-        match periods {
-            Ok(periods) => Ok(Doug {
-                periods,
-                settings,
-                settings_location,
-            }),
-            // No periods exist. Create a new Doug instance.
-            Err(ref error) if error.is_eof() => Ok(Doug {
-                periods: Vec::new(),
-                settings,
-                settings_location,
-            }),
-            Err(error) => Err(format!("There was a serialization issue: {:?}\n", error)),
+        let loaded = schema::read(data_file)?;
+        Ok(Doug::from_periods(
+            loaded.periods,
+            settings,
+            settings_location,
+        ))
+    }
+
+    fn from_periods(
+        mut periods: Vec<Period>,
+        settings: settings::Settings,
+        settings_location: PathBuf,
+    ) -> Self {
+        assign_ids(&mut periods);
+        Doug {
+            saved: periods.clone(),
+            periods,
+            settings,
+            settings_location,
+            storage: None,
+            lock: None,
+            complete: true,
+            latest: true,
         }
     }
 
@@ -152,11 +562,14 @@ impl Doug {
     ///
     /// If there is no running project, logs `No running project`. The CLI returns exit code 1.
     ///
+    /// The last period is read from the state sidecar when it's up to date, so no periods are
+    /// loaded. See [state].
+    ///
     /// See arguments to refine the output of this command.
     ///
     /// # Arguments
     /// * `simple_name` — Print just the name of the currently running project,
-    /// or nothing.
+    ///   or nothing.
     /// * `simple_time` — Print just the current time, formated with [format::duration].
     ///
     /// # Examples
@@ -173,7 +586,7 @@ impl Doug {
     /// // with no running project, this will return Err
     /// doug.status(false, false).expect_err("No running project");
     ///
-    /// doug.start("test");
+    /// doug.start("test", &[], None);
     ///
     /// // no args
     /// doug.status(false, false).expect("Should return Ok");
@@ -184,10 +597,10 @@ impl Doug {
     /// // simple_time
     /// doug.status(false, true).expect("Should be fine too");
     ///
-    /// # doug.stop();
+    /// # doug.stop(None);
     /// ```
-    pub fn status(&self, simple_name: bool, simple_time: bool) -> DougResult {
-        if let Some(period) = &self.periods.last() {
+    pub fn status(&mut self, simple_name: bool, simple_time: bool) -> DougResult {
+        if let Some(period) = &self.last_period()? {
             if period.end_time.is_none() {
                 let diff = Utc::now().signed_duration_since(period.start_time);
                 let message = if simple_name {
@@ -212,19 +625,116 @@ impl Doug {
         }
     }
 
-    pub fn settings(&mut self, path: Option<&str>, clear: bool) -> DougResult {
+    /// Show or change settings.
+    ///
+    /// See [SettingsOptions] for the settings that can be changed.
+    pub fn settings(&mut self, options: &SettingsOptions) -> DougResult {
+        let SettingsOptions {
+            path,
+            clear,
+            keep_last,
+            keep_daily,
+            git,
+            remote,
+            key_file,
+            shard_by,
+            first_weekday,
+            ref rates,
+            ref remove_rates,
+            currency,
+        } = *options;
         if clear {
             self.settings.clear(&self.settings_location)?;
             return Ok(Some("Cleared settings file".to_string()));
         }
         if let Some(path) = path {
+            self.require(&storage::Range::All)?;
             DirBuilder::new()
                 .recursive(true)
-                .create(&path)
+                .create(path)
                 .map_err(|err| format!("Couldn't create data directory: {:?}\n", err))?;
             self.settings.data_location = PathBuf::from(path);
             self.settings.save(&self.settings_location)?;
-            self.save()?;
+            let mut storage = storage::open(&self.settings)?;
+            storage.load()?;
+            self.storage = Some(storage);
+            if self.settings.git {
+                git::init(&self.settings.data_location)?;
+            }
+            self.write()?;
+            self.commit(&format!("move data to {}", path))?;
+        }
+        if let Some(git) = git {
+            if git {
+                git::init(&self.settings.data_location)?;
+            }
+            self.settings.git = git;
+            self.settings.save(&self.settings_location)?;
+            self.commit("add period data")?;
+        }
+        if let Some(remote) = remote {
+            self.settings.remote = Some(remote.to_string());
+            self.settings.save(&self.settings_location)?;
+        }
+        if let Some(key_file) = key_file {
+            self.settings.key_file = Some(PathBuf::from(key_file));
+            self.settings.save(&self.settings_location)?;
+        }
+        if let Some(shard_by) = shard_by {
+            self.require(&storage::Range::All)?;
+            self.settings.shard_by = shard_by;
+            self.settings.save(&self.settings_location)?;
+            if self.settings.storage == storage::StorageKind::Json {
+                // the existing shards are loaded, so saving replaces them with the new ones
+                let mut storage = storage::open(&self.settings)?;
+                storage.load()?;
+                self.storage = Some(storage);
+                self.write()?;
+                self.commit(&format!("shard periods by {:?}", shard_by).to_lowercase())?;
+            }
+        }
+        if let Some(first_weekday) = first_weekday {
+            self.settings.first_weekday = Some(first_weekday);
+            self.settings.save(&self.settings_location)?;
+        }
+        if !rates.is_empty() || !remove_rates.is_empty() || currency.is_some() {
+            let settings_rates = &mut self.settings.rates;
+            for (name, rate) in rates {
+                match name.strip_prefix('+') {
+                    Some(tag) => settings_rates.tags.insert(tag.to_string(), *rate),
+                    None => settings_rates.projects.insert(name.to_string(), *rate),
+                };
+            }
+            for name in remove_rates {
+                let removed = match name.strip_prefix('+') {
+                    Some(tag) => settings_rates.tags.remove(tag),
+                    None => settings_rates.projects.remove(*name),
+                };
+                if removed.is_none() {
+                    return Err(format!("No rate set for {}", name));
+                }
+            }
+            if let Some(currency) = currency {
+                settings_rates.currency = Some(currency.to_string());
+            }
+            self.settings.save(&self.settings_location)?;
+        }
+        if keep_last.is_some() || keep_daily.is_some() {
+            let mut backups = self.settings.backups.clone();
+            if let Some(keep_last) = keep_last {
+                backups.keep_last = keep_last;
+            }
+            if let Some(keep_daily) = keep_daily {
+                backups.keep_daily = keep_daily;
+            }
+            if backups.keep_last == 0 && backups.keep_daily == 0 {
+                return Err(
+                    "Backups would all be removed. Keep at least one with --keep-last or --keep-daily."
+                        .to_string(),
+                );
+            }
+            self.settings.backups = backups;
+            self.settings.save(&self.settings_location)?;
         }
         Ok(Some(format!(
             "{}:\n{:#?}",
@@ -235,29 +745,385 @@ impl Doug {
 
     /// Save period data to file.
     ///
-    /// A backup of the data file will be made before serializing the data.
-    pub fn save(&mut self) -> DougResult {
-        // sort our periods before we save. This helps with merging.
-        self.periods.sort_by(|a, b| a.start_time.cmp(&b.start_time));
-        let serialized = serde_json::to_string(&self.periods)
-            .map_err(|_| "Couldn't serialize data to string".to_string())?;
-        let mut location_backup = self.data_location();
-        location_backup.set_extension("json-backup");
-        fs::copy(&self.data_location(), &location_backup)
-            .map_err(|err| format!("Couldn't create backup file: {:?}", err))?;
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&self.data_location())
-            .map_err(|err| format!("Couldn't open file for saving period: {:?}", err))?;
-        file.write_all(serialized.as_bytes())
-            .map_err(|_| "Couldn't write serialized data to file".to_string())?;
+    /// The last period is recorded in the state sidecar, see [state].
+    ///
+    /// The files that change are backed up before saving, and old backups are pruned
+    /// according to [settings::Settings::backups]. How the data is written depends on the
+    /// storage backend, see [storage]. The journal isn't backed up, since saving only appends
+    /// to it.
+    ///
+    /// The change since the last save is added to the undo history, see [undo].
+    ///
+    /// # Arguments
+    /// * `description` — what changed, shown when the change is undone or redone.
+    pub fn save(&mut self, description: &str) -> DougResult {
+        self.write()?;
+        self.commit(description)?;
+        let operation = undo::Operation::new(description, &self.saved, &self.periods);
+        if !operation.is_empty() {
+            undo::History::append(
+                &self.settings.data_location,
+                self.history_passphrase()?.as_deref(),
+                operation,
+            )?;
+        }
+        self.saved = self.periods.clone();
         Ok(None)
     }
 
+    /// Save period data without recording undo history.
+    fn write(&mut self) -> Result<(), String> {
+        // sort our periods before we save. This helps with merging.
+        self.periods.sort_by_key(|a| a.start_time);
+        assign_ids(&mut self.periods);
+        let storage = self
+            .storage
+            .as_mut()
+            .ok_or_else(|| "Periods weren't loaded from a data directory".to_string())?;
+        let changed: Vec<PathBuf> = storage
+            .changed_files(&self.periods)
+            .into_iter()
+            .filter(|location| location.is_file())
+            .collect();
+        if !changed.is_empty() {
+            let backup_folder = backup::folder(&self.settings.data_location);
+            for location in &changed {
+                backup::create(&backup_folder, location)?;
+            }
+            backup::prune(&backup_folder, &self.settings.backups)?;
+        }
+        storage.save(&self.periods)?;
+        self.save_state()
+    }
+
+    /// Back up the whole data file of backends that don't back up on every save, before a
+    /// change to many periods at once.
+    ///
+    /// Returns the backup, or `None` if saving backs up the data on its own.
+    fn backup_data_file(&self) -> Result<Option<backup::Backup>, String> {
+        let location = self.data_location();
+        let backs_up_on_save = match &self.storage {
+            Some(storage) => !storage.changed_files(&self.periods).is_empty(),
+            None => true,
+        };
+        if backs_up_on_save || !location.is_file() {
+            return Ok(None);
+        }
+        let backup_folder = backup::folder(&self.settings.data_location);
+        let backup = backup::create(&backup_folder, &location)?;
+        backup::prune(&backup_folder, &self.settings.backups)?;
+        Ok(Some(backup))
+    }
+
+    fn load_history(&self) -> Result<undo::History, String> {
+        undo::History::load(
+            &self.settings.data_location,
+            self.history_passphrase()?.as_deref(),
+        )
+    }
+
+    fn save_history(&self, history: &undo::History) -> Result<(), String> {
+        history.save(
+            &self.settings.data_location,
+            self.history_passphrase()?.as_deref(),
+        )
+    }
+
+    /// Passphrase for the undo history, which is encrypted along with the data file
+    fn history_passphrase(&self) -> Result<Option<String>, String> {
+        if self.settings.storage == storage::StorageKind::Encrypted {
+            crypto::passphrase(&self.settings).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Commit the data file if the data directory is tracked with git.
+    ///
+    /// The single `periods.json` of older versions is included, so its removal after
+    /// migrating to shards is committed too.
+    fn commit(&self, description: &str) -> Result<(), String> {
+        if self.settings.git {
+            let mut files = vec![Path::new(self.settings.storage.file_name())];
+            if self.settings.storage == storage::StorageKind::Json {
+                files.push(Path::new("periods.json"));
+            }
+            git::commit(&self.settings.data_location, &files, description)?;
+        }
+        Ok(())
+    }
+
     fn data_location(&self) -> PathBuf {
-        self.settings.data_location.clone().join("periods.json")
+        self.settings
+            .data_location
+            .join(self.settings.storage.file_name())
+    }
+
+    /// Move periods to another storage backend.
+    ///
+    /// The old data file is left in place. If the new backend already has data, it's backed up
+    /// before being replaced.
+    ///
+    /// # Arguments
+    /// * `kind` — backend to store periods in from now on.
+    pub fn migrate_storage(&mut self, kind: storage::StorageKind) -> DougResult {
+        if kind == self.settings.storage {
+            return Err(format!(
+                "Already using {} storage",
+                format!("{:?}", kind).to_lowercase()
+            ));
+        }
+        self.require(&storage::Range::All)?;
+        let old_location = self.data_location();
+        let mut storage = storage::open_kind(kind, &self.settings)?;
+        if !storage.load()?.is_empty() {
+            let mut locations = storage.changed_files(&self.periods);
+            if locations.is_empty() {
+                // the backend doesn't back up on save, so keep the whole data file
+                locations.push(storage.location().to_path_buf());
+            }
+            for location in locations {
+                if location.is_file() {
+                    backup::create(&backup::folder(&self.settings.data_location), &location)?;
+                }
+            }
+        }
+        storage.save(&self.periods)?;
+        let message = format!(
+            "Moved {} periods to {}\nThe old data file is still at {}\n",
+            self.periods.len(),
+            storage.location().to_string_lossy().blue(),
+            old_location.to_string_lossy()
+        );
+        self.storage = Some(storage);
+        self.settings.storage = kind;
+        self.settings.save(&self.settings_location)?;
+        self.save_state()?;
+        self.commit(&format!("move periods to {:?} storage", kind).to_lowercase())?;
+        Ok(Some(message))
+    }
+
+    /// Revert the last change to periods.
+    ///
+    /// Fails if the periods it changed were changed again outside of doug.
+    pub fn undo(&mut self) -> DougResult {
+        let mut history = self.load_history()?;
+        let operation = history
+            .undo
+            .pop()
+            .ok_or_else(|| "Nothing to undo".to_string())?;
+        self.apply_operation(
+            &operation.reversed(),
+            &format!("undo {}", operation.description),
+        )?;
+        let message = format!(
+            "Undid {}\n{}",
+            operation.description.blue(),
+            diff::render(&diff::diff(&operation.after, &operation.before))
+        );
+        history.redo.push(operation);
+        self.save_history(&history)?;
+        Ok(Some(message))
+    }
+
+    /// Reapply the last change reverted by [Doug::undo].
+    pub fn redo(&mut self) -> DougResult {
+        let mut history = self.load_history()?;
+        let operation = history
+            .redo
+            .pop()
+            .ok_or_else(|| "Nothing to redo".to_string())?;
+        self.apply_operation(&operation, &format!("redo {}", operation.description))?;
+        let message = format!(
+            "Redid {}\n{}",
+            operation.description.blue(),
+            diff::render(&diff::diff(&operation.before, &operation.after))
+        );
+        history.undo.push(operation);
+        self.save_history(&history)?;
+        Ok(Some(message))
+    }
+
+    fn apply_operation(
+        &mut self,
+        operation: &undo::Operation,
+        description: &str,
+    ) -> Result<(), String> {
+        self.require(&storage::Range::All)?;
+        operation.apply(&mut self.periods)?;
+        self.write()?;
+        self.commit(description)?;
+        self.saved = self.periods.clone();
+        Ok(())
+    }
+
+    /// Encrypt the data file, its backups and the undo history.
+    ///
+    /// The passphrase is read from the key file in settings, or from `DOUG_PASSPHRASE`. The
+    /// unencrypted data file and backups are overwritten and removed. Other copies of periods
+    /// in the data directory, see [storage::leftovers], are moved into encrypted backups.
+    pub fn encrypt(&mut self) -> DougResult {
+        if self.settings.storage == storage::StorageKind::Encrypted {
+            return Err("The data file is already encrypted".to_string());
+        }
+        crypto::passphrase(&self.settings)?;
+        self.convert_encryption(storage::StorageKind::Encrypted)
+    }
+
+    /// Decrypt the data file, its backups and the undo history, storing periods as json.
+    ///
+    /// The encrypted data file and backups are removed.
+    pub fn decrypt(&mut self) -> DougResult {
+        if self.settings.storage != storage::StorageKind::Encrypted {
+            return Err("The data file isn't encrypted".to_string());
+        }
+        self.convert_encryption(storage::StorageKind::Json)
+    }
+
+    fn convert_encryption(&mut self, kind: storage::StorageKind) -> DougResult {
+        self.require(&storage::Range::All)?;
+        let encrypting = kind == storage::StorageKind::Encrypted;
+        let history = self.load_history()?;
+        let old_location = self.data_location();
+        let old_kind = self.settings.storage;
+
+        let mut storage = storage::open_kind(kind, &self.settings)?;
+        storage.save(&self.periods)?;
+        let location = storage.location().to_path_buf();
+        self.storage = Some(storage);
+        self.settings.storage = kind;
+        self.settings.save(&self.settings_location)?;
+        self.save_history(&history)?;
+        self.save_state()?;
+        // unencrypted copies are overwritten before they're removed
+        let remove = |location: &Path| {
+            if encrypting {
+                file::remove_securely(location)
+            } else if location.is_dir() {
+                fs::remove_dir_all(location)
+                    .map_err(|err| format!("Couldn't remove {:?}: {:?}", location, err))
+            } else {
+                fs::remove_file(location)
+                    .map_err(|err| format!("Couldn't remove {:?}: {:?}", location, err))
+            }
+        };
+        remove(&old_location)?;
+
+        let mut converted = 0;
+        for backup in backup::list(&backup::folder(&self.settings.data_location))? {
+            let is_encrypted = backup
+                .location
+                .extension()
+                .is_some_and(|extension| extension == "enc");
+            if is_encrypted == encrypting {
+                continue;
+            }
+            let periods = storage::open_file(&backup.location, &self.settings)?.load()?;
+            let extension = if encrypting { "enc" } else { "json" };
+            storage::open_file(&backup.location.with_extension(extension), &self.settings)?
+                .save(&periods)?;
+            remove(&backup.location)?;
+            converted += 1;
+        }
+
+        // older data files and migration originals would keep the periods readable
+        let mut leftovers = Vec::new();
+        let mut failed = Vec::new();
+        if encrypting {
+            let backup_folder = backup::folder(&self.settings.data_location);
+            for leftover in storage::leftovers(&self.settings.data_location)? {
+                let moved = storage::load_leftover(&leftover, &self.settings).and_then(|periods| {
+                    let modified = fs::metadata(&leftover)
+                        .and_then(|metadata| metadata.modified())
+                        .map_err(|err| format!("Couldn't read {:?}: {:?}", leftover, err))?;
+                    backup::save(&backup_folder, &periods, modified.into(), &self.settings)?;
+                    remove(&leftover)
+                });
+                match moved {
+                    Ok(()) => leftovers.push(leftover),
+                    Err(err) => failed.push((leftover, err)),
+                }
+            }
+        }
+
+        let verb = if encrypting { "Encrypted" } else { "Decrypted" };
+        let mut message = format!(
+            "{} {} periods to {}\n{} {} backups\n",
+            verb,
+            self.periods.len(),
+            location.to_string_lossy().blue(),
+            verb,
+            converted
+        );
+        if !leftovers.is_empty() {
+            message.push_str(
+                format!(
+                    "Moved {} unencrypted copies of periods into encrypted backups:\n",
+                    leftovers.len()
+                )
+                .as_str(),
+            );
+            for leftover in &leftovers {
+                message.push_str(format!("  {}\n", leftover.to_string_lossy()).as_str());
+            }
+        }
+        for (leftover, err) in &failed {
+            message.push_str(
+                format!(
+                    "Couldn't move {} into a backup, remove it yourself: {}\n",
+                    leftover.to_string_lossy().red(),
+                    err
+                )
+                .as_str(),
+            );
+        }
+        if encrypting {
+            message.push_str("Unencrypted files were overwritten before they were removed\n");
+        }
+        if self.settings.git {
+            let mut files = vec![Path::new(old_kind.file_name()), Path::new(kind.file_name())];
+            files.extend(
+                leftovers.iter().filter_map(|leftover| {
+                    leftover.strip_prefix(&self.settings.data_location).ok()
+                }),
+            );
+            git::commit(
+                &self.settings.data_location,
+                &files,
+                &format!("{} data file", verb.to_lowercase()),
+            )?;
+            if encrypting {
+                message.push_str("Earlier git commits still contain the unencrypted data file\n");
+            }
+        }
+        Ok(Some(message))
+    }
+
+    /// Replace the journal with one event per period.
+    ///
+    /// The journal is backed up first.
+    pub fn compact(&mut self) -> DougResult {
+        if self.settings.storage != storage::StorageKind::Journal {
+            return Err("Only journal storage can be compacted".to_string());
+        }
+        self.require(&storage::Range::All)?;
+        self.periods.sort_by_key(|a| a.start_time);
+        assign_ids(&mut self.periods);
+        let backup_folder = backup::folder(&self.settings.data_location);
+        let location_backup = backup::create(&backup_folder, &self.data_location())?.location;
+        let storage = self
+            .storage
+            .as_mut()
+            .ok_or_else(|| "Periods weren't loaded from a data directory".to_string())?;
+        let removed = storage.compact(&self.periods)?;
+        backup::prune(&backup_folder, &self.settings.backups)?;
+        self.commit("compact journal")?;
+        Ok(Some(format!(
+            "Backed up journal to {}\nRemoved {} events, {} periods remain\n",
+            location_backup.to_string_lossy().blue(),
+            removed,
+            self.periods.len()
+        )))
     }
 
     /// Start tracking a project.
@@ -266,7 +1132,28 @@ impl Doug {
     ///
     /// # Arguments
     /// * `project_name` — name of project to start tracking a new period with.
-    pub fn start(&mut self, project_name: &str) -> DougResult {
+    /// * `tags` — tags for the new period, each prefixed with `+` (e.g. `+billable`).
+    /// * `note` — optional note describing the work.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate tempfile;
+    /// # extern crate doug;
+    /// # let tempdir = tempfile::tempdir().unwrap().into_path();
+    /// # let tempdir = tempdir.to_string_lossy();
+    /// # use doug::*;
+    /// # let mut doug = Doug::new(Some(&tempdir)).unwrap();
+    /// // tags must be prefixed with '+'
+    /// doug.start("api", &["billable"], None).expect_err("Invalid tag");
+    ///
+    /// doug.start("api", &["+review", "+billable"], Some("code review"))
+    ///     .expect("Should return Ok");
+    /// # doug.stop(None);
+    /// ```
+    pub fn start(&mut self, project_name: &str, tags: &[&str], note: Option<&str>) -> DougResult {
+        self.require_latest()?;
+        let tags = parse_tags(tags)?;
         if !self.periods.is_empty() {
             if let Some(period) = self.periods.last_mut() {
                 if period.end_time.is_none() {
@@ -282,14 +1169,121 @@ impl Doug {
                 }
             }
         }
-        let current_period = Period::new(project_name);
-        let message = format!(
-            "Started tracking project {} at {}\n",
-            current_period.project.blue(),
-            format::time(current_period.start_time)
-        );
-        self.periods.push(current_period);
-        self.save()?;
+        let mut current_period = Period::new(project_name, &tags);
+        if let Some(note) = note {
+            current_period.add_note(note);
+        }
+        let mut message = format!(
+            "Started tracking project {} at {}\n",
+            current_period.project.blue(),
+            format::time(current_period.start_time)
+        );
+        if !current_period.tags.is_empty() {
+            message.push_str(format!("Tags: {}\n", current_period.tag_list().yellow()).as_str());
+        }
+        let description = format!("start {}", current_period.project);
+        self.periods.push(current_period);
+        self.save(&description)?;
+        Ok(Some(message))
+    }
+
+    /// Add a finished period after the fact.
+    ///
+    /// The period is inserted in chronological order. Periods ending in the future are refused,
+    /// as are periods overlapping the running period. Adding a period that overlaps another
+    /// existing one is refused unless [AddOptions::force] is set, and the overlaps are listed.
+    ///
+    /// # Arguments
+    /// * `project_name` — project of the new period.
+    /// * `tags` — tags for the new period, each prefixed with `+`.
+    /// * `options` — when the period happened. See [AddOptions].
+    pub fn add(&mut self, project_name: &str, tags: &[&str], options: &AddOptions) -> DougResult {
+        self.require(&storage::Range::All)?;
+        let tags = parse_tags(tags)?;
+        let duration = options.duration.map(format::parse_duration).transpose()?;
+        let from = options.from.map(parse_date).transpose()?;
+        let to = options.to.map(parse_date).transpose()?;
+        let (start_time, end_time) = match (from, to, duration) {
+            (Some(from), Some(to), None) => (from, to),
+            (Some(_), Some(_), Some(_)) => {
+                return Err("Use either --from and --to, or a duration.".to_string())
+            }
+            (Some(from), None, Some(duration)) => (from, from + duration),
+            (None, Some(to), Some(duration)) => (to - duration, to),
+            (None, None, Some(duration)) => {
+                let day = match options.on {
+                    Some(on) => parse_date(on)?.date(),
+                    None => Local::now().date(),
+                };
+                let start = self
+                    .periods
+                    .iter()
+                    .filter(|period| period.start_time.with_timezone(&Local).date() == day)
+                    .map(|period| period.end_or_now().with_timezone(&Local))
+                    .max()
+                    .unwrap_or_else(|| day.and_hms(9, 0, 0));
+                (start, start + duration)
+            }
+            _ => return Err("Missing time range. Use --from and --to, or a duration.".to_string()),
+        };
+        if end_time <= start_time {
+            return Err("Period would end before it starts.".to_string());
+        }
+        if end_time > Local::now() {
+            return Err(format!(
+                "Period would end in the future, at {}. Use {} to track ongoing work.",
+                format::datetime(end_time.with_timezone(&Utc)),
+                "start".blue()
+            ));
+        }
+
+        let mut period = Period::new(project_name, &tags);
+        period.start_time = start_time.with_timezone(&Utc);
+        period.end_time = Some(end_time.with_timezone(&Utc));
+        period.id = period.generate_id(0);
+        if let Some(note) = options.note {
+            period.add_note(note);
+        }
+
+        let overlapping: Vec<&Period> = self
+            .periods
+            .iter()
+            .filter(|other| other.overlaps(&period))
+            .collect();
+        // the running period would end up overlapping the new one, even with --force
+        if let Some(running) = overlapping.iter().find(|other| other.end_time.is_none()) {
+            let mut error = format!("project {} is being tracked\n", running.project);
+            error.push_str(
+                format!(
+                    "Try stopping your current project with {} first.",
+                    "stop".blue()
+                )
+                .as_str(),
+            );
+            return Err(error);
+        }
+        if !overlapping.is_empty() && !options.force {
+            let mut error = "period overlaps existing periods:\n".to_string();
+            for other in overlapping {
+                error.push_str(format!("    {}\n", other.summary()).as_str());
+            }
+            error.push_str(format!("Use {} to add it anyway.", "--force".blue()).as_str());
+            return Err(error);
+        }
+
+        let mut message = format!("Added {}\n", period.summary());
+        if !overlapping.is_empty() {
+            message.push_str("Overlaps existing periods:\n");
+            for other in overlapping {
+                message.push_str(format!("    {}\n", other.summary()).as_str());
+            }
+        }
+        let description = format!("add {}", period.project);
+        let index = self
+            .periods
+            .partition_point(|other| other.start_time <= period.start_time);
+        self.periods.insert(index, period);
+        self.save(&description)?;
         Ok(Some(message))
     }
 
@@ -297,6 +1291,7 @@ impl Doug {
     ///
     /// Will exit 1 if there isn't any running project.
     pub fn amend(&mut self, project_name: &str) -> DougResult {
+        self.require_latest()?;
         if let Some(mut period) = self.periods.pop() {
             if period.end_time.is_none() {
                 let old_name = period.project.clone();
@@ -306,70 +1301,207 @@ impl Doug {
                     old = old_name.red(),
                     new = period.project.green()
                 );
+                let description = format!("amend {} to {}", old_name, period.project);
                 self.periods.push(period);
-                self.save()?;
+                self.save(&description)?;
                 return Ok(Some(message));
             }
         }
         Err("No project started".to_string())
     }
 
-    /// Aggregate periods per project.
-    pub fn report(
-        &self,
-        past_years: i32,
-        past_months: i32,
-        past_weeks: i32,
-        past_days: i32,
-        from_date: Option<&str>,
-        to_date: Option<&str>,
+    /// Rename a project in every period, including finished ones.
+    ///
+    /// # Arguments
+    /// * `old` — project to rename. Accepts patterns like `acme-*`.
+    /// * `new` — new project name. Each `*` is replaced with the text matched by the
+    ///   corresponding `*` in `old`, so `acme-*` → `acme/*` renames `acme-web` to `acme/web`.
+    /// * `since` — only rename periods starting on or after this date.
+    /// * `until` — only rename periods starting on or before this date.
+    /// * `dry_run` — list the periods that would be renamed without changing them.
+    pub fn rename(
+        &mut self,
+        old: &str,
+        new: &str,
+        since: Option<&str>,
+        until: Option<&str>,
+        dry_run: bool,
     ) -> DougResult {
-        let (from_date, to_date): (Date<Local>, Date<Local>) =
-            if past_years > 0 || past_months > 0 || past_weeks > 0 || past_days > 0 {
-                let duration = Duration::weeks((52_i32 * past_years).into())
-                    + Duration::weeks((4_i32 * past_months).into())
-                    + Duration::weeks(past_weeks.into())
-                    + Duration::days(past_days.into());
-                let today = Local::now().date();
-                let start = today - duration;
-
-                (start, today)
-            } else {
-                let from_date_parsed: Date<Local> = {
-                    if let Some(from) = from_date {
-                        parse_date_string(&from, Local::now(), Dialect::Us)
-                            .map_err(|_| format!("Couldn't parse date {}", from))?
-                            .date()
-                    } else {
-                        Utc.from_utc_date(&NaiveDate::from_ymd(1, 1, 1))
-                            .with_timezone(&Local)
-                    }
-                };
-                let to_date_parsed: Date<Local> = {
-                    if let Some(to) = to_date {
-                        parse_date_string(&to, Local::now(), Dialect::Us)
-                            .map_err(|_| format!("Couldn't parse date {}", to))?
-                            .date()
-                    } else {
-                        Local::now().date()
-                    }
-                };
+        if new.matches('*').count() > old.matches('*').count() {
+            return Err(format!("{} has more '*' than {}", new, old));
+        }
+        let since = since.map(parse_date).transpose()?.map(|date| date.date());
+        let until = until.map(parse_date).transpose()?.map(|date| date.date());
+        self.require(&storage::Range::Between(
+            since.map(|since| since.and_hms(0, 0, 0).with_timezone(&Utc)),
+            until.map(|until| {
+                (until + Duration::days(1))
+                    .and_hms(0, 0, 0)
+                    .with_timezone(&Utc)
+            }),
+        ))?;
 
-                (from_date_parsed, to_date_parsed)
+        let mut message = String::new();
+        let mut renamed = 0;
+        for period in self.periods.iter_mut() {
+            let date = period.start_time.with_timezone(&Local).date();
+            if since.is_some_and(|since| date < since) || until.is_some_and(|until| date > until) {
+                continue;
+            }
+            let new_name = match project::rename(old, new, &period.project) {
+                Some(new_name) if new_name != period.project => new_name,
+                _ => continue,
             };
-
-        let mut days: HashMap<ProjectName, Vec<Period>> = HashMap::new();
-        // organize periods by project
-        for period in &self.periods {
-            days.entry(period.project.clone())
-                .or_insert_with(Vec::new)
-                .push(period.clone());
+            message.push_str(
+                format!(
+                    "    {id} {date} {period} {old} -> {new}\n",
+                    id = period.id.dimmed(),
+                    date = format::date(period.start_time),
+                    period = period,
+                    old = period.project.red(),
+                    new = new_name.green()
+                )
+                .as_str(),
+            );
+            if !dry_run {
+                period.project = new_name;
+            }
+            renamed += 1;
+        }
+        if renamed == 0 {
+            return Err("No matching periods found.".to_string());
+        }
+        if dry_run {
+            message
+                .push_str(format!("dry run set. {} periods would be renamed.\n", renamed).as_str());
+        } else {
+            self.save(&format!("rename {} to {}", old, new))?;
+            message.push_str(format!("Renamed {} periods\n", renamed).as_str());
         }
+        Ok(Some(message))
+    }
 
-        let mut results: Vec<(ProjectName, Duration)> = Vec::new();
+    /// Aggregate periods per project, or per tag.
+    ///
+    /// Projects are shown as a tree, split on `/`, where every parent includes the time
+    /// of its children. Periods running across the edges of the date range only count the
+    /// time inside it. See [ReportOptions] to limit the date range or filter periods, and
+    /// [report::Format] for the output formats. With [ReportOptions::billing], billable
+    /// amounts are shown instead, see [billing::Bill].
+    pub fn report(&mut self, options: &ReportOptions) -> DougResult {
+        let ReportOptions {
+            past_years,
+            past_months,
+            past_weeks,
+            past_days,
+            from_date,
+            to_date,
+            ..
+        } = *options;
+        let first_weekday = self.settings.first_weekday();
+        let unbounded = options.named.is_none()
+            && past_years == 0
+            && past_months == 0
+            && past_weeks == 0
+            && past_days == 0
+            && from_date.is_none();
+        let (from_date, to_date): (Date<Local>, Date<Local>) = if let Some(named) = options.named {
+            named.dates(Local::now().date(), first_weekday)
+        } else if past_years > 0 || past_months > 0 || past_weeks > 0 || past_days > 0 {
+            let today = Local::now().date();
+            let start = calendar::add_months(today, -(12 * past_years + past_months))
+                - Duration::weeks(past_weeks.into())
+                - Duration::days(past_days.into());
+
+            (start, today)
+        } else {
+            let from_date_parsed: Date<Local> = {
+                if let Some(from) = from_date {
+                    parse_date(from)?.date()
+                } else {
+                    Utc.from_utc_date(&NaiveDate::from_ymd(1, 1, 1))
+                        .with_timezone(&Local)
+                }
+            };
+            let to_date_parsed: Date<Local> = {
+                if let Some(to) = to_date {
+                    parse_date(to)?.date()
+                } else {
+                    Local::now().date()
+                }
+            };
+
+            (from_date_parsed, to_date_parsed)
+        };
+        let range_start = from_date.and_hms(0, 0, 0).with_timezone(&Utc);
+        let range_end = (to_date + Duration::days(1))
+            .and_hms(0, 0, 0)
+            .with_timezone(&Utc);
+        self.require(&storage::Range::Overlapping(
+            Some(range_start),
+            Some(range_end),
+        ))?;
+
+        let mut days: HashMap<String, Vec<Period>> = HashMap::new();
+        // organize periods by project or tag
+        let included = |period: &Period| {
+            period.has_tags(&options.tags)
+                && (options.projects.is_empty()
+                    || options
+                        .projects
+                        .iter()
+                        .any(|pattern| project::matches(pattern, &period.project)))
+        };
+        // only count the time of periods inside the range
+        let periods: Vec<Period> = self
+            .periods
+            .iter()
+            .filter(|period| included(period))
+            .filter_map(|period| period.clip(Some(range_start), Some(range_end)))
+            .flat_map(|period| match options.split_overnight {
+                true => period.split_days(),
+                false => vec![period],
+            })
+            .collect();
+        if options.billing {
+            let from = match periods.first() {
+                Some(first) if unbounded => first.start_time.with_timezone(&Local).date(),
+                _ if unbounded => to_date,
+                _ => from_date,
+            };
+            let bill = billing::Bill::new(&periods, &self.settings.rates, from, to_date);
+            return billing::render(&bill, options.format).map(Some);
+        }
+        let mut total = Duration::zero();
+        for period in &periods {
+            let start_date = period.start_time.with_timezone(&Local).date();
+            if from_date <= start_date && start_date <= to_date {
+                total = total
+                    + period
+                        .end_time
+                        .unwrap_or_else(Utc::now)
+                        .signed_duration_since(period.start_time);
+            }
+            match options.group_by {
+                GroupBy::Project => days
+                    .entry(period.project.clone())
+                    .or_default()
+                    .push(period.clone()),
+                GroupBy::Tag if period.tags.is_empty() => days
+                    .entry("(untagged)".to_string())
+                    .or_default()
+                    .push(period.clone()),
+                GroupBy::Tag => {
+                    for tag in &period.tags {
+                        days.entry(format!("+{}", tag))
+                            .or_default()
+                            .push(period.clone());
+                    }
+                }
+            }
+        }
 
-        let mut max_proj_len = 0;
-        let mut max_diff_len = 0;
+        let mut results: Vec<(String, Duration)> = Vec::new();
 
         // start of the earliest interval
         let mut min_start_date = Local::now().date();
@@ -400,46 +1532,101 @@ impl Doug {
                 continue;
             }
 
-            // find lengths of project names for alignment
-            max_proj_len = max(project.to_string().len(), max_proj_len);
-            // find lengths of durations names for alignment
-            max_diff_len = max(
-                format::duration(duration).len(),
-                format::duration(duration).len(),
-            );
-
             results.push((project.clone(), duration));
         }
-        let mut message = format!(
-            "{start} -> {end}\n",
-            start = min_start_date.format("%A %-d %B %Y").to_string().blue(),
-            end = to_date.format("%A %-d %B %Y").to_string().blue()
-        );
-        results.sort();
-        for (project, duration) in &results {
-            message.push_str(
-                format!(
-                    "{project:pwidth$} {duration:>dwidth$}\n",
-                    project = project.green(),
-                    duration = format::duration(*duration).bold(),
-                    pwidth = max_proj_len,
-                    dwidth = max_diff_len
-                )
-                .as_str(),
-            );
+
+        let mut rows: Vec<report::Row> = match options.group_by {
+            GroupBy::Project => {
+                let mut tree = project::Tree::default();
+                for (project, duration) in &results {
+                    tree.insert(project, *duration);
+                }
+                // full names of the parents of the current row
+                let mut parents: Vec<&str> = Vec::new();
+                tree.rows(options.depth)
+                    .into_iter()
+                    .map(|(level, name, duration, own)| {
+                        parents.truncate(level);
+                        parents.push(name);
+                        report::Row {
+                            name: parents.join("/"),
+                            level,
+                            duration,
+                            own,
+                            buckets: Vec::new(),
+                        }
+                    })
+                    .collect()
+            }
+            GroupBy::Tag => {
+                results.sort();
+                results
+                    .into_iter()
+                    .map(|(name, duration)| report::Row {
+                        name,
+                        level: 0,
+                        duration,
+                        own: duration,
+                        buckets: Vec::new(),
+                    })
+                    .collect()
+            }
+        };
+
+        let report_from = if unbounded { min_start_date } else { from_date };
+        let columns = options.by.map_or_else(Vec::new, |by| {
+            by.columns(report_from, to_date, first_weekday)
+        });
+        let mut column_totals = Vec::new();
+        if options.by.is_some() {
+            column_totals = buckets(periods.iter(), &columns, from_date, to_date);
+            let buckets_by_name: Vec<(&String, Vec<Duration>)> = days
+                .iter()
+                .map(|(name, periods)| {
+                    (name, buckets(periods.iter(), &columns, from_date, to_date))
+                })
+                .collect();
+            for row in &mut rows {
+                row.buckets = vec![Duration::zero(); columns.len()];
+                let child_prefix = format!("{}/", row.name);
+                for (name, buckets) in &buckets_by_name {
+                    // rows include the time of their child projects
+                    let in_row = **name == row.name
+                        || (options.group_by == GroupBy::Project
+                            && name.starts_with(&child_prefix));
+                    if in_row {
+                        for (cell, duration) in row.buckets.iter_mut().zip(buckets) {
+                            *cell = *cell + *duration;
+                        }
+                    }
+                }
+            }
         }
-        Ok(Some(message))
+
+        let report = report::Report {
+            from: report_from,
+            to: to_date,
+            first: min_start_date,
+            group_by: options.group_by,
+            rows,
+            total,
+            by: options.by,
+            columns,
+            column_totals,
+        };
+        report::render(&report, options.format).map(Some)
     }
 
     /// Remove all periods for a project
     ///
     /// # Arguments
-    /// * `project_name` — project to remove
+    /// * `project_name` — project to remove. Accepts patterns like `client/*`.
     pub fn delete(&mut self, project_name: &str) -> DougResult {
+        self.require(&storage::Range::All)?;
         let mut project_not_found = true;
         let mut filtered_periods = Vec::new();
         for period in &self.periods {
-            if period.project == project_name {
+            if project::matches(project_name, &period.project) {
                 project_not_found = false;
             } else {
                 filtered_periods.push(period.clone());
@@ -449,7 +1636,7 @@ impl Doug {
             Err("Project not found.\n".to_string())
         } else {
             self.periods = filtered_periods;
-            self.save()?;
+            self.save(&format!("delete {}", project_name))?;
             Ok(Some(format!(
                 "Deleted project {project}\n",
                 project = project_name.blue()
@@ -457,15 +1644,33 @@ impl Doug {
         }
     }
 
+    /// Remove a single period
+    ///
+    /// # Arguments
+    /// * `id` — id, or unique prefix of the id, of the period to remove
+    pub fn delete_period(&mut self, id: &str) -> DougResult {
+        self.require(&storage::Range::All)?;
+        let index = self.find_period(id)?;
+        let period = self.periods.remove(index);
+        self.save(&format!("delete period {}", period.id))?;
+        Ok(Some(format!(
+            "Deleted period {id} {period} {project}\n",
+            id = period.id.dimmed(),
+            period = period,
+            project = period.project.blue()
+        )))
+    }
+
     /// Restart last running period
     pub fn restart(&mut self) -> DougResult {
+        self.require_latest()?;
         let mut new_periods = self.periods.to_vec();
         if let Some(period) = self.periods.clone().last() {
             if period.end_time.is_some() {
-                let new_period = Period::new(&period.project);
+                let new_period = Period::new(&period.project, &period.tags);
                 new_periods.push(new_period);
                 self.periods = new_periods.to_vec();
-                self.save()?;
+                self.save(&format!("restart {}", period.project))?;
                 return Ok(Some(format!(
                     "Tracking last running project: {}",
                     period.project.blue()
@@ -489,15 +1694,52 @@ impl Doug {
     }
 
     /// List periods in chronological order
-    pub fn log(&self) -> DougResult {
+    ///
+    /// Periods running across the edges of the date range are cut short at them. See
+    /// [LogOptions] to limit the date range or filter periods.
+    pub fn log(&mut self, options: &LogOptions) -> DougResult {
+        let from_date = options
+            .from_date
+            .map(parse_date)
+            .transpose()?
+            .map(|date| date.date());
+        let to_date = options
+            .to_date
+            .map(parse_date)
+            .transpose()?
+            .map(|date| date.date());
+        let range_start = from_date.map(|from| from.and_hms(0, 0, 0).with_timezone(&Utc));
+        let range_end = to_date.map(|to| {
+            (to + Duration::days(1))
+                .and_hms(0, 0, 0)
+                .with_timezone(&Utc)
+        });
+        self.require(&storage::Range::Overlapping(range_start, range_end))?;
         let mut days: HashMap<Date<chrono::Local>, Vec<Period>> = HashMap::new();
+        let grep = options.grep.map(str::to_lowercase);
 
+        // only list the time of periods inside the range
+        let periods = self
+            .periods
+            .iter()
+            .filter_map(|period| period.clip(range_start, range_end))
+            .flat_map(|period| match options.split_overnight {
+                true => period.split_days(),
+                false => vec![period],
+            });
         // organize periods by day
-        for period in &self.periods {
-            let time = period.start_time.with_timezone(&Local).date();
-            days.entry(time)
-                .or_insert_with(Vec::new)
-                .push(period.clone());
+        for period in periods {
+            let date = period.start_time.with_timezone(&Local).date();
+            if from_date.is_some_and(|from| date < from) || to_date.is_some_and(|to| date > to) {
+                continue;
+            }
+            if let Some(pattern) = &grep {
+                match &period.note {
+                    Some(note) if note.to_lowercase().contains(pattern.as_str()) => {}
+                    _ => continue,
+                }
+            }
+            days.entry(date).or_default().push(period);
         }
 
         // order days
@@ -527,6 +1769,11 @@ impl Doug {
             // find time tracker per period
             let mut project_periods = Vec::new();
             for period in day.iter() {
+                let tags = if period.tags.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {}", period.tag_list().yellow())
+                };
                 // push periods onto vector so we can could there lengths and properly align them
                 match period.end_time {
                     Some(end_time) => {
@@ -539,11 +1786,13 @@ impl Doug {
                         ));
                         message.push_str(
                             format!(
-                                "    {start} to {end} {diff:>width$} {project}\n",
+                                "    {id} {start} to {end} {diff:>width$} {project}{tags}\n",
+                                id = period.id.dimmed(),
                                 start = format::time(period.start_time),
                                 end = format::time(end_time),
                                 diff = format::duration(diff),
                                 project = period.project.clone().blue(),
+                                tags = tags,
                                 width = 11
                             )
                             .as_str(),
@@ -553,17 +1802,24 @@ impl Doug {
                         let diff = Utc::now().signed_duration_since(period.start_time);
                         message.push_str(
                             format!(
-                                "    {start} to {end} {diff:>width$} {project}\n",
+                                "    {id} {start} to {end} {diff:>width$} {project}{tags}\n",
+                                id = period.id.dimmed(),
                                 start = format::time(period.start_time),
                                 end = format::time(Utc::now()),
                                 diff = format::duration(diff),
                                 project = period.project.clone().blue(),
+                                tags = tags,
                                 width = 11
                             )
                             .as_str(),
                         );
                     }
                 }
+                if let Some(note) = &period.note {
+                    for line in note.lines() {
+                        message.push_str(format!("        {}\n", line.italic()).as_str());
+                    }
+                }
             }
         }
         Ok(Some(message))
@@ -571,9 +1827,10 @@ impl Doug {
 
     /// Stop current period and remove log entry
     pub fn cancel(&mut self) -> DougResult {
+        self.require_latest()?;
         match self.periods.pop() {
             Some(ref mut period) if period.end_time.is_none() => {
-                self.save()?;
+                self.save(&format!("cancel {}", period.project))?;
                 let diff = Utc::now().signed_duration_since(period.start_time);
                 Ok(Some(format!(
                     "Canceled project {}, started {} ago",
@@ -586,10 +1843,17 @@ impl Doug {
     }
 
     /// Stop current period and save stop time
-    pub fn stop(&mut self) -> DougResult {
+    ///
+    /// # Arguments
+    /// * `note` — optional note to append to the period.
+    pub fn stop(&mut self, note: Option<&str>) -> DougResult {
+        self.require_latest()?;
         match self.periods.pop() {
             Some(ref mut period) if period.end_time.is_none() => {
                 period.end_time = Some(Utc::now());
+                if let Some(note) = note {
+                    period.add_note(note);
+                }
                 let diff = Utc::now().signed_duration_since(period.start_time);
                 let messaage = format!(
                     "Stopped project {}, started {} ago",
@@ -597,129 +1861,630 @@ impl Doug {
                     format::duration(diff)
                 );
                 self.periods.push(period.clone());
-                self.save()?;
+                self.save(&format!("stop {}", period.project))?;
                 Ok(Some(messaage))
             }
             _ => Err("No project started.".to_string()),
         }
     }
 
-    /// Retrieve last active (including current) period
-    fn last_period(&mut self) -> Option<&mut Period> {
-        self.periods.last_mut()
+    /// Append a note to the currently running period
+    pub fn note(&mut self, note: &str) -> DougResult {
+        self.require_latest()?;
+        match self.periods.last_mut() {
+            Some(period) if period.end_time.is_none() => {
+                period.add_note(note);
+                let message = format!("Added note to project {}", period.project.blue());
+                let description = format!("add note to {}", period.project);
+                self.save(&description)?;
+                Ok(Some(message))
+            }
+            _ => Err("No project started.".to_string()),
+        }
+    }
+
+    /// Find the index of a period by id or by a unique prefix of its id.
+    fn find_period(&self, id: &str) -> Result<usize, String> {
+        if let Some(index) = self.periods.iter().position(|period| period.id == id) {
+            return Ok(index);
+        }
+        let matches: Vec<usize> = self
+            .periods
+            .iter()
+            .enumerate()
+            .filter(|(_, period)| !id.is_empty() && period.id.starts_with(id))
+            .map(|(index, _)| index)
+            .collect();
+        match matches.as_slice() {
+            [index] => Ok(*index),
+            [] => Err(format!("No period with id {}", id)),
+            _ => Err(format!("Period id {} is ambiguous", id)),
+        }
     }
 
-    /// Edit last running period.
+    /// Edit a period, or the last running period.
     ///
-    /// If no arguments are provided, the data file is open `$EDITOR`.
+    /// If no arguments are provided, the shard holding the last period is opened in `$EDITOR`.
     ///
     /// # Arguments
-    /// * `start` — date to set start time of last period.
-    /// * `end` — date to set end time of last period.
+    /// * `id` — id of the period to edit. Defaults to the last period.
+    /// * `start` — date to set start time of the period.
+    /// * `end` — date to set end time of the period.
+    /// * `project` — new project name for the period.
     ///
-    /// Both arguments accept humanized dates (e.g. `thursday 9:00am`, `today 12:15pm`)
-    pub fn edit(&mut self, start: Option<&str>, end: Option<&str>) -> DougResult {
+    /// Both dates accept humanized dates (e.g. `thursday 9:00am`, `today 12:15pm`). Like
+    /// [Doug::split], changed times are refused if the period would end before it starts or
+    /// overlap another period.
+    pub fn edit(
+        &mut self,
+        id: Option<&str>,
+        start: Option<&str>,
+        end: Option<&str>,
+        project: Option<&str>,
+    ) -> DougResult {
+        if start.is_none() && end.is_none() && project.is_none() {
+            if id.is_some() {
+                return Err("Nothing to edit. Use --start, --end or --project.".to_string());
+            }
+            if self.settings.storage != storage::StorageKind::Json {
+                return Err("Editing the data file is only supported with json storage".to_string());
+            }
+            // the shard holding the latest period
+            let shard = self.settings.shard_by.shard(
+                self.last_period()?
+                    .map_or_else(Utc::now, |period| period.start_time),
+            );
+            let location = self.data_location().join(format!("{}.json", shard));
+            let message = format!(
+                "File: {}\n",
+                location.to_str().ok_or("Invalid path")?.blue()
+            );
+            let editor = env::var("EDITOR").map_err(|_| "Couldn't open editor".to_string())?;
+            let mut edit = Command::new(editor);
+            edit.arg(location);
+            edit.status()
+                .map_err(|_| "Problem with editing.".to_string())?;
+            return Ok(Some(message));
+        }
+
+        self.require(&storage::Range::All)?;
+        let index = match id {
+            Some(id) => self.find_period(id)?,
+            None if self.periods.is_empty() => return Err("no period to edit".to_string()),
+            None => self.periods.len() - 1,
+        };
+        let mut period = self.periods[index].clone();
         if let Some(start) = start {
-            let date = parse_date_string(start, Local::now(), Dialect::Us)
-                .map_err(|_| format!("Couldn't parse date {}", start))?;
-            let period = self
-                .last_period()
-                .ok_or_else(|| "no period to edit".to_string())?;
+            let date = parse_date(start)?;
             period.start_time = date.with_timezone(&Utc);
         }
-
         if let Some(end) = end {
-            let date = parse_date_string(end, Local::now(), Dialect::Us)
-                .map_err(|_| format!("Couldn't parse date {}", end))?;
-            let period = self
-                .last_period()
-                .ok_or_else(|| "no period to edit".to_string())?;
+            let date = parse_date(end)?;
             period.end_time = Some(date.with_timezone(&Utc));
         }
+        if let Some(project) = project {
+            period.project = String::from(project);
+        }
         if start.is_some() || end.is_some() {
-            self.save()?;
-            return Ok(Some(
-                self.clone()
-                    .last_period()
-                    .ok_or_else(|| "Couldn't find last period.".to_string())?
-                    .to_string(),
+            self.validate(&[&period], &[])?;
+        }
+        let message = format!(
+            "{} {} {}",
+            period.id.dimmed(),
+            period,
+            period.project.blue()
+        );
+        let description = format!("edit period {}", period.id);
+        self.periods[index] = period;
+        self.save(&description)?;
+        Ok(Some(message))
+    }
+
+    /// Refuse periods that run backwards or overlap other periods.
+    ///
+    /// # Arguments
+    /// * `periods` — new or changed periods.
+    /// * `ignore` — ids of periods being replaced, which aren't checked for overlaps.
+    fn validate(&self, periods: &[&Period], ignore: &[&str]) -> Result<(), String> {
+        for period in periods {
+            if period.end_or_now() < period.start_time {
+                return Err(format!("Period would end before it starts: {}", period));
+            }
+            let overlapping = self.periods.iter().find(|other| {
+                !ignore.contains(&other.id.as_str())
+                    && other.id != period.id
+                    && other.overlaps(period)
+            });
+            if let Some(other) = overlapping {
+                return Err(format!("Period would overlap {}", other.summary()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Cut a period in two.
+    ///
+    /// The second half keeps the tags of the period, and is still running if the period was.
+    ///
+    /// # Arguments
+    /// * `id` — id of the period to split.
+    /// * `at` — humanized date to split at (e.g. `14:00`).
+    /// * `project` — optional project name for the second half.
+    pub fn split(&mut self, id: &str, at: &str, project: Option<&str>) -> DougResult {
+        self.require(&storage::Range::All)?;
+        let index = self.find_period(id)?;
+        let at = parse_date(at)?.with_timezone(&Utc);
+        let mut first = self.periods[index].clone();
+        if at <= first.start_time || at >= first.end_or_now() {
+            return Err(format!(
+                "{} isn't during period {} {}",
+                format::datetime(at),
+                first.id,
+                first
             ));
         }
+        let mut second = Period::new(project.unwrap_or(&first.project), &first.tags);
+        second.start_time = at;
+        second.end_time = first.end_time;
+        second.id = second.generate_id(0);
+        first.end_time = Some(at);
+        self.validate(&[&first, &second], &[&first.id])?;
+
         let message = format!(
-            "File: {}\n",
-            self.data_location().to_str().ok_or("Invalid path")?.blue()
+            "Split period {id}\n    {first_id} {first} {first_project}\n    {second_id} {second} {second_project}\n",
+            id = id,
+            first_id = first.id.dimmed(),
+            first = first,
+            first_project = first.project.blue(),
+            second_id = second.id.dimmed(),
+            second = second,
+            second_project = second.project.blue()
         );
-        let editor = env::var("EDITOR").map_err(|_| "Couldn't open editor".to_string())?;
-        let mut edit = Command::new(editor);
-        edit.arg(self.data_location().clone());
-        edit.status()
-            .map_err(|_| "Problem with editing.".to_string())?;
+        self.periods[index] = first;
+        self.periods.insert(index + 1, second);
+        self.save(&format!("split period {}", id))?;
         Ok(Some(message))
     }
 
-    /// Merge period file
+    /// Join two consecutive periods of the same project into one.
+    ///
+    /// The joined period keeps the id of the earlier period. Tags are combined and notes are
+    /// appended.
+    ///
+    /// # Arguments
+    /// * `allow_gap` — join periods with time between them, counting that time as tracked.
+    ///   Without it, the second period has to start when the first one ends.
+    pub fn join(&mut self, first_id: &str, second_id: &str, allow_gap: bool) -> DougResult {
+        self.require(&storage::Range::All)?;
+        let mut indexes = [self.find_period(first_id)?, self.find_period(second_id)?];
+        indexes.sort_by_key(|index| self.periods[*index].start_time);
+        let [first_index, second_index] = indexes;
+        if first_index == second_index {
+            return Err("Can't join a period with itself".to_string());
+        }
+        let (first, second) = (&self.periods[first_index], &self.periods[second_index]);
+        if first.project != second.project {
+            return Err(format!(
+                "Can't join periods of different projects: {} and {}",
+                first.project.blue(),
+                second.project.blue()
+            ));
+        }
+        if self.periods.iter().any(|other| {
+            other.start_time > first.start_time && other.start_time < second.start_time
+        }) {
+            return Err("Can only join consecutive periods".to_string());
+        }
+
+        let mut joined = first.clone();
+        joined.end_time = second.end_time;
+        for tag in &second.tags {
+            if !joined.tags.contains(tag) {
+                joined.tags.push(tag.clone());
+            }
+        }
+        if let Some(note) = &second.note {
+            joined.add_note(note);
+        }
+        let gap = second.start_time.signed_duration_since(first.end_or_now());
+        if gap > Duration::zero() && !allow_gap {
+            return Err(format!(
+                "Periods are {} apart. Use --allow-gap to count that time as tracked.",
+                format::duration(gap).trim()
+            ));
+        }
+        self.validate(&[&joined], &[&first.id, &second.id])?;
+
+        let mut message = format!(
+            "Joined periods into {} {} {}\n",
+            joined.id.dimmed(),
+            joined,
+            joined.project.blue()
+        );
+        if gap > Duration::zero() {
+            message
+                .push_str(format!("Includes a gap of {}\n", format::duration(gap).trim()).as_str());
+        }
+        self.periods[first_index] = joined;
+        self.periods.remove(second_index);
+        self.save(&format!("join periods {} and {}", first_id, second_id))?;
+        Ok(Some(message))
+    }
+
+    /// Find a backup by its number in [Doug::backup_list], starting at 1 for the newest.
+    fn find_backup(&self, number: usize) -> Result<backup::Backup, String> {
+        let backups = backup::list(&backup::folder(&self.settings.data_location))?;
+        number
+            .checked_sub(1)
+            .and_then(|index| backups.get(index).cloned())
+            .ok_or_else(|| format!("No backup {}. There are {} backups.", number, backups.len()))
+    }
+
+    fn load_backup(&self, backup: &backup::Backup) -> Result<Vec<Period>, String> {
+        let mut periods = storage::open_file_read_only(&backup.location, &self.settings)?.load()?;
+        assign_ids(&mut periods);
+        Ok(periods)
+    }
+
+    /// List backups of the data file, newest first. Backups of a single shard show its name.
+    pub fn backup_list(&self) -> DougResult {
+        let backups = backup::list(&backup::folder(&self.settings.data_location))?;
+        if backups.is_empty() {
+            return Ok(Some("No backups\n".to_string()));
+        }
+        let mut message = String::new();
+        for (index, backup) in backups.iter().enumerate() {
+            message.push_str(
+                format!(
+                    "{number:>4} {created} {shard:>7} {location}\n",
+                    number = index + 1,
+                    shard = backup.shard.as_deref().unwrap_or("all"),
+                    created = backup
+                        .created
+                        .with_timezone(&Local)
+                        .format("%F %H:%M:%S")
+                        .to_string()
+                        .blue(),
+                    location = backup.location.to_string_lossy().dimmed()
+                )
+                .as_str(),
+            );
+        }
+        Ok(Some(message))
+    }
+
+    /// Show changes between a backup and the current data.
+    ///
+    /// # Arguments
+    /// * `number` — backup number from [Doug::backup_list], starting at 1 for the newest.
+    pub fn backup_diff(&mut self, number: usize) -> DougResult {
+        self.require(&storage::Range::All)?;
+        let backup = self.find_backup(number)?;
+        let current: Vec<Period> = self
+            .periods
+            .iter()
+            .filter(|period| {
+                backup
+                    .shard
+                    .as_ref()
+                    .is_none_or(|shard| storage::in_shard(shard, period.start_time))
+            })
+            .cloned()
+            .collect();
+        let changes = diff::diff(&self.load_backup(&backup)?, &current);
+        if changes.is_empty() {
+            return Ok(Some("No changes since backup\n".to_string()));
+        }
+        Ok(Some(format!(
+            "Changes since backup from {}:\n{}",
+            format::datetime(backup.created).blue(),
+            diff::render(&changes)
+        )))
+    }
+
+    /// Replace the current data with a backup. A backup of a shard only replaces the periods
+    /// in that shard.
+    ///
+    /// The current data is backed up first, so a restore can be undone by restoring that backup.
+    ///
+    /// # Arguments
+    /// * `number` — backup number from [Doug::backup_list], starting at 1 for the newest.
+    pub fn backup_restore(&mut self, number: usize) -> DougResult {
+        self.require(&storage::Range::All)?;
+        let backup = self.find_backup(number)?;
+        let periods = self.load_backup(&backup)?;
+        match &backup.shard {
+            Some(shard) => {
+                self.periods
+                    .retain(|period| !storage::in_shard(shard, period.start_time));
+                self.periods.extend(periods);
+            }
+            None => self.periods = periods,
+        }
+        self.backup_data_file()?;
+        self.save(&format!("restore backup {}", number))?;
+        Ok(Some(format!(
+            "Restored backup from {}\n",
+            format::datetime(backup.created).blue()
+        )))
+    }
+
+    /// Validate period data
+    ///
+    /// Reports running periods that aren't last, periods that end before they start, overlapping
+    /// periods and duplicate start times.
     ///
-    /// If two periods have conflicting end times, the one with the earlier end time will be used.
+    /// Fails if any problems are left, which with `fix` are the ones that need a decision,
+    /// listed with how to repair them by hand.
+    ///
+    /// # Arguments
+    /// * `fix` — apply safe repairs (see [check::fix]). The data file is backed up first.
+    pub fn check(&mut self, fix: bool) -> DougResult {
+        self.require(&storage::Range::All)?;
+        let mut message = String::new();
+        let mut fixed = self.periods.clone();
+        let repairs = if fix {
+            check::fix(&mut fixed)
+        } else {
+            Vec::new()
+        };
+        if !repairs.is_empty() {
+            self.periods = fixed;
+            // other backends back up the files they change on save
+            let location_backup = match self.backup_data_file()? {
+                Some(backup) => backup.location,
+                None => backup::folder(&self.settings.data_location),
+            };
+            self.save("check --fix")?;
+            message.push_str(
+                format!(
+                    "Backed up data to {}\n",
+                    location_backup.to_string_lossy().blue()
+                )
+                .as_str(),
+            );
+            for repair in repairs {
+                message.push_str(format!("{} {}\n", "fixed:".green(), repair).as_str());
+            }
+        }
+        let problems = check::check(&self.periods);
+        if problems.is_empty() {
+            message.push_str("No problems found\n");
+            return Ok(Some(message));
+        }
+        if fix {
+            message.push_str(
+                format!(
+                    "{} problems can't be fixed automatically:\n",
+                    problems.len()
+                )
+                .as_str(),
+            );
+            for problem in problems {
+                message.push_str(format!("    {} {}\n", "not fixed:".red(), problem).as_str());
+                message.push_str(format!("        {}\n", problem.hint()).as_str());
+            }
+            return Err(message);
+        }
+        message.push_str(format!("Found {} problems:\n", problems.len()).as_str());
+        for problem in problems {
+            message.push_str(format!("    {}\n", problem).as_str());
+        }
+        message.push_str(format!("Try {} to repair them.", "check --fix".blue()).as_str());
+        Err(message)
+    }
+
+    /// Show differences between period files.
     ///
-    pub fn merge(&mut self, file_path: &str, dry_run: bool) -> DougResult {
-        // Open other period file as Doug instance
+    /// # Arguments
+    /// * `file_path` — data file to compare.
+    /// * `other_file_path` — data file to compare `file_path` to. Defaults to the current data.
+    /// * `json` — output the differences as JSON instead of one line per period.
+    pub fn diff(
+        &mut self,
+        file_path: &str,
+        other_file_path: Option<&str>,
+        json: bool,
+    ) -> DougResult {
+        let (left_name, left, right_name, right) = match other_file_path {
+            Some(other_file_path) => (
+                file_path.to_string(),
+                self.load_other_file(file_path)?,
+                other_file_path.to_string(),
+                self.load_other_file(other_file_path)?,
+            ),
+            None => {
+                self.require(&storage::Range::All)?;
+                (
+                    self.data_location().to_string_lossy().into_owned(),
+                    self.periods.clone(),
+                    file_path.to_string(),
+                    self.load_other_file(file_path)?,
+                )
+            }
+        };
+        let changes = diff::diff(&left, &right);
+        if json {
+            let mut value = diff::to_json(&changes);
+            value["left"] = left_name.into();
+            value["right"] = right_name.into();
+            return serde_json::to_string_pretty(&value)
+                .map(Some)
+                .map_err(|_| "Couldn't serialize data to string".to_string());
+        }
+        let mut message = format!(
+            "{} {}\n{} {}\n",
+            "---".red(),
+            left_name,
+            "+++".green(),
+            right_name
+        );
+        if changes.is_empty() {
+            message.push_str("No differences\n");
+        } else {
+            message.push_str(&diff::render(&changes));
+        }
+        Ok(Some(message))
+    }
+
+    /// Load periods from a data file of any storage backend, without changing the file.
+    fn load_other_file(&self, file_path: &str) -> Result<Vec<Period>, String> {
         let location = Path::new(file_path);
+        if location.is_dir() {
+            return storage::open_file(location, &self.settings)?.load();
+        }
         let data_file = OpenOptions::new()
             .read(true)
-            .open(&location)
+            .open(location)
             .map_err(|_| format!("Couldn't open datafile: {:?}\n", location))?;
-        let empty_settings = settings::Settings::default();
-        let folder = Path::new("/tmp/doug/empty_settings").to_path_buf();
-        let other_doug = Doug::load_periods_from_file(&data_file, empty_settings, folder)?;
-
-        let mut other_period_map = HashMap::new();
-        for period in other_doug.periods.iter() {
-            other_period_map.insert(period.start_time, period);
-        }
-        let mut self_period_map = HashMap::new();
-        for period in self.periods.iter() {
-            self_period_map.insert(period.start_time, period);
-        }
-
-        // merge both period collections into one
-        let mut merged: Vec<Period> = Vec::new();
-        for (start_time, other_period) in other_period_map.iter() {
-            match self_period_map.get(&start_time) {
-                Some(self_period) => {
-                    // default case. Both files have matching periods
-                    if self_period == other_period {
-                        merged.push((*self_period).clone());
-                    }
-                    // choose the shortest end time first
-                    else if self_period.end_time > other_period.end_time {
-                        eprintln!(
-                            "choosing other period ({}) over self ({})",
-                            other_period, self_period
-                        );
-                        merged.push((*other_period).clone());
-                    } else if self_period.end_time < other_period.end_time {
-                        eprintln!(
-                            "choosing self period ({}) over other ({})",
-                            self_period, other_period
-                        );
-                        merged.push((*self_period).clone());
+        match location
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("sqlite") | Some("journal") | Some("enc") => {
+                storage::open_file_read_only(location, &self.settings)?.load()
+            }
+            _ => {
+                let empty_settings = settings::Settings::default();
+                let folder = Path::new("/tmp/doug/empty_settings").to_path_buf();
+                Ok(Doug::load_periods_from_file(&data_file, empty_settings, folder)?.periods)
+            }
+        }
+    }
+
+    /// Pull and push period data with the git remote from settings.
+    ///
+    /// If both sides have new commits, the remote data file is merged into the local one as
+    /// with [Doug::merge], and the result is committed as a merge.
+    ///
+    /// # Arguments
+    /// * `strategy` — how to resolve conflicting periods, see [merge::Strategy].
+    pub fn sync(&mut self, strategy: merge::Strategy) -> DougResult {
+        if !self.settings.git {
+            return Err("Git isn't enabled. Use doug settings --git on".to_string());
+        }
+        let remote = self
+            .settings
+            .remote
+            .clone()
+            .ok_or_else(|| "No remote set. Use doug settings --remote <url>".to_string())?;
+        self.require(&storage::Range::All)?;
+        let folder = self.settings.data_location.clone();
+        let branch = git::branch(&folder)?;
+        let mut message = String::new();
+        match git::fetch(&folder, &remote, &branch)? {
+            None | Some(git::State::Ahead) => {}
+            Some(git::State::Behind) => {
+                git::fast_forward(&folder)?;
+                let mut storage = storage::open(&self.settings)?;
+                let mut periods = storage.load()?;
+                self.storage = Some(storage);
+                assign_ids(&mut periods);
+                message.push_str(&diff::render(&diff::diff(&self.periods, &periods)));
+                self.saved = periods.clone();
+                self.periods = periods;
+            }
+            Some(git::State::Diverged) => {
+                let file_name = Path::new(self.settings.storage.file_name());
+                // the data file, or every shard of it
+                let mut other_periods = Vec::new();
+                for file in git::fetched_files(&folder, file_name)? {
+                    let fetched = env::temp_dir().join(format!(
+                        "doug-sync-{}-{}",
+                        std::process::id(),
+                        file.to_string_lossy().replace('/', "-")
+                    ));
+                    if git::show_fetched(&folder, &file, &fetched)? {
+                        let periods = self.load_other_file(&fetched.to_string_lossy());
+                        fs::remove_file(&fetched)
+                            .map_err(|err| format!("Couldn't remove {:?}: {:?}", fetched, err))?;
+                        other_periods.extend(periods?);
                     }
                 }
-                _ => {
-                    eprintln!(
-                        "adding period not in self: {} {}",
-                        other_period.start_time, other_period
-                    );
-                    merged.push((*other_period).clone());
+                let report =
+                    merge::merge(&self.periods, &other_periods, |ours, theirs| match strategy
+                        .pick(ours, theirs)
+                    {
+                        Some(side) => Ok(side),
+                        None => ask_merge_side(ours, theirs),
+                    })?;
+                message.push_str(&merge::render(&report));
+                git::start_merge(&folder)?;
+                self.periods = report.merged;
+                if let Some(storage) = self.storage.as_mut() {
+                    storage.merging(Path::new(&remote));
                 }
+                self.save(&format!("sync with {}", remote))?;
             }
         }
-        if dry_run {
-            Ok(Some("dry run set. not applying changes.".into()))
+        git::push(&folder, &remote, &branch)?;
+        message.push_str(format!("Synced with {}\n", remote.blue()).as_str());
+        Ok(Some(message))
+    }
+
+    /// Merge period file
+    ///
+    /// Periods from both files are kept. If both files have a different period starting at the
+    /// same time, `strategy` picks the one to keep. Conflicts, and merged periods that overlap
+    /// others, are listed in the output.
+    ///
+    /// # Arguments
+    /// * `file_path` — data file to merge into this one.
+    /// * `strategy` — how to resolve conflicts, see [merge::Strategy].
+    /// * `dry_run` — show the merge result without changing the data file.
+    pub fn merge(
+        &mut self,
+        file_path: &str,
+        strategy: merge::Strategy,
+        dry_run: bool,
+    ) -> DougResult {
+        self.require(&storage::Range::All)?;
+        let location = Path::new(file_path);
+        let other_periods = self.load_other_file(file_path)?;
+        let report = merge::merge(&self.periods, &other_periods, |ours, theirs| match strategy
+            .pick(ours, theirs)
+        {
+            Some(side) => Ok(side),
+            None => ask_merge_side(ours, theirs),
+        })?;
+        let mut message = merge::render(&report);
+        if report.is_empty() {
+            message.push_str("Nothing to merge\n");
+        } else if dry_run {
+            message.push_str("dry run set. not applying changes.\n");
         } else {
-            self.periods = merged;
-            self.save()?;
-            Ok(Some("changes applied".into()))
+            self.periods = report.merged;
+            if let Some(storage) = self.storage.as_mut() {
+                storage.merging(location);
+            }
+            self.save(&format!("merge {}", file_path))?;
+            message.push_str("changes applied\n");
+        }
+        Ok(Some(message))
+    }
+}
+
+/// Ask which of two conflicting periods to keep when merging.
+fn ask_merge_side(ours: &Period, theirs: &Period) -> Result<merge::Side, String> {
+    let stdin = io::stdin();
+    loop {
+        eprint!(
+            "Conflicting periods:\n  self  {}\n  other {}\nKeep [s]elf or [o]ther? ",
+            ours.summary(),
+            theirs.summary()
+        );
+        let mut answer = String::new();
+        let read = stdin
+            .lock()
+            .read_line(&mut answer)
+            .map_err(|err| format!("Couldn't read answer: {:?}", err))?;
+        if read == 0 {
+            return Err("Merge canceled".to_string());
+        }
+        match answer.trim() {
+            "s" | "self" => return Ok(merge::Side::Ours),
+            "o" | "other" => return Ok(merge::Side::Theirs),
+            _ => {}
         }
     }
 }
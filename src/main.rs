@@ -3,12 +3,99 @@
 use std::io::stdout;
 
 use atty::Stream;
-use clap::{crate_authors, crate_version, App, AppSettings, Arg, Shell, SubCommand};
+use clap::{
+    crate_authors, crate_version, App, AppSettings, Arg, ArgGroup, ArgMatches, Shell, SubCommand,
+};
 use colored::Colorize;
 
+use doug::calendar::NamedRange;
 use doug::*;
 use std::process;
 
+/// Collect all values of a (possibly missing) argument
+fn values<'a>(matches: &'a ArgMatches, name: &str) -> Vec<&'a str> {
+    matches
+        .values_of(name)
+        .map(|values| values.collect())
+        .unwrap_or_default()
+}
+
+/// Validate that an argument is a positive number
+fn is_positive(value: String) -> Result<(), String> {
+    match value.parse::<usize>() {
+        Ok(number) if number > 0 => Ok(()),
+        _ => Err("must be a positive number".to_string()),
+    }
+}
+
+/// Validate that an argument is a number, including 0
+fn is_number(value: String) -> Result<(), String> {
+    value
+        .parse::<usize>()
+        .map(|_| ())
+        .map_err(|_| "must be a number".to_string())
+}
+
+/// Validate that an argument is a project or `+tag` with an hourly rate (e.g. `client=120`)
+fn is_rate(value: String) -> Result<(), String> {
+    match value.split_once('=') {
+        Some((name, rate)) if !name.is_empty() && name != "+" => {
+            rate.parse::<billing::Money>().map(|_| ())
+        }
+        _ => Err("must be a project or +tag and an hourly rate (e.g. client=120)".to_string()),
+    }
+}
+
+/// Parse an argument validated with [is_number] or [is_positive]
+fn number(matches: &ArgMatches, name: &str) -> Option<usize> {
+    matches.value_of(name).map(|value| value.parse().unwrap())
+}
+
+/// Number of extra `m`s in `-mm`, which is read as `-m` with the value `m`
+fn stacked_months(value: &str) -> Option<usize> {
+    if value.chars().all(|c| c == 'm') {
+        Some(value.len())
+    } else {
+        None
+    }
+}
+
+/// Number of past months for `report`, from `-m`, `-mm` or `--month --month`. A month given
+/// as a value is a named range instead.
+fn past_months(matches: &ArgMatches) -> i32 {
+    let occurrences = matches.occurrences_of("month") as i32;
+    match matches.value_of("month") {
+        Some(value) => stacked_months(value).map_or(0, |extra| occurrences + extra as i32),
+        None => occurrences,
+    }
+}
+
+/// Find the calendar range picked with one of the named range arguments of `report`
+fn named_range(matches: &ArgMatches) -> Option<NamedRange> {
+    let flags = [
+        ("this-week", NamedRange::ThisWeek),
+        ("last-week", NamedRange::LastWeek),
+        ("this-month", NamedRange::ThisMonth),
+        ("last-month", NamedRange::LastMonth),
+    ];
+    if let Some((_, range)) = flags.iter().find(|(name, _)| matches.is_present(name)) {
+        return Some(*range);
+    }
+    // values were checked by the argument validators
+    let range = if let Some(quarter) = matches.value_of("quarter") {
+        NamedRange::quarter(quarter)
+    } else if let Some(week) = matches.value_of("iso-week") {
+        NamedRange::iso_week(week)
+    } else {
+        let month = matches.value_of("month")?;
+        if stacked_months(month).is_some() {
+            return None;
+        }
+        NamedRange::month(month)
+    };
+    range.ok()
+}
+
 fn main() {
     if !atty::is(Stream::Stdout) {
         colored::control::set_override(false);
@@ -36,7 +123,59 @@ fn main() {
                     .about("Track new or existing project")
                     .arg(Arg::with_name("project").help(
                         "project to track. If missing, start subcommand behaves like restart.",
-                    )),
+                    ))
+                    .arg(
+                        Arg::with_name("tags")
+                            .help("tags for the new period (e.g. +review +billable)")
+                            .multiple(true),
+                    ).arg(
+                        Arg::with_name("message")
+                            .short("m")
+                            .long("message")
+                            .help("note describing the work")
+                            .takes_value(true),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("add")
+                    .about("Add a period that wasn't tracked")
+                    .arg(
+                        Arg::with_name("project")
+                            .help("project of the new period")
+                            .required(true),
+                    ).arg(
+                        Arg::with_name("args")
+                            .value_name("duration")
+                            .help("length of the period (e.g. 1h30m) and tags (e.g. +billable)")
+                            .multiple(true),
+                    ).arg(
+                        Arg::with_name("from")
+                            .short("f")
+                            .long("from")
+                            .help("starting date (e.g. \"today 9:00\")")
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("to")
+                            .short("t")
+                            .long("to")
+                            .help("ending date (e.g. \"today 10:30\")")
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("on")
+                            .long("on")
+                            .help("day of the period when only a duration is given (default: today)")
+                            .conflicts_with_all(&["from", "to"])
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("message")
+                            .short("m")
+                            .long("message")
+                            .help("note describing the work")
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("force")
+                            .long("force")
+                            .help("add the period even if it overlaps existing periods"),
+                    ),
             ).subcommand(
                 SubCommand::with_name("status")
                     .about("Display elapsed time, start time, and running project name")
@@ -48,15 +187,56 @@ fn main() {
                     ).arg(Arg::with_name("s").short("s").long("simple").help(
                         "Print running project name or nothing if there isn't a running project.",
                     )),
-            ).subcommand(SubCommand::with_name("stop").about("Stop any running projects"))
-            .subcommand(SubCommand::with_name("s").about("Stop any running projects").settings(&[AppSettings::Hidden, AppSettings::HidePossibleValuesInHelp]))
+            ).subcommand(
+                SubCommand::with_name("stop")
+                    .about("Stop any running projects")
+                    .arg(
+                        Arg::with_name("message")
+                            .short("m")
+                            .long("message")
+                            .help("note to add to the stopped period")
+                            .takes_value(true),
+                    ),
+            ).subcommand(SubCommand::with_name("s").about("Stop any running projects").settings(&[AppSettings::Hidden, AppSettings::HidePossibleValuesInHelp]))
+            .subcommand(
+                SubCommand::with_name("note")
+                    .about("Add a note to the running project")
+                    .arg(
+                        Arg::with_name("message")
+                            .help("note describing the work")
+                            .required(true),
+                    ),
+            )
             .subcommand(
                 SubCommand::with_name("cancel")
                     .about("Stop running project and remove most recent time interval"),
             ).subcommand(SubCommand::with_name("restart").about("Track last running project"))
             .subcommand(SubCommand::with_name("r").about("Track last running project").settings(&[AppSettings::Hidden, AppSettings::HidePossibleValuesInHelp]))
             .subcommand(
-                SubCommand::with_name("log").about("Display time intervals across all projects"),
+                SubCommand::with_name("log")
+                    .about("Display time intervals across all projects")
+                    .arg(
+                        Arg::with_name("grep")
+                            .long("grep")
+                            .help("Only display intervals with a note matching pattern")
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("from")
+                            .short("f")
+                            .long("from")
+                            .help("first day to display (e.g. \"last monday\")")
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("to")
+                            .short("t")
+                            .long("to")
+                            .help("last day to display (e.g. \"yesterday\")")
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("split-overnight")
+                            .long("split-overnight")
+                            .help("List periods running past midnight under each day they cover"),
+                    ),
             ).subcommand(
                 SubCommand::with_name("report")
                     .about("Display aggregate time from projects")
@@ -71,8 +251,16 @@ fn main() {
                         Arg::with_name("month")
                             .short("m")
                             .long("month")
-                            .help("Limit report to past month. Use multiple to increase interval.")
+                            .value_name("month")
+                            .help("Limit report to past month. Use multiple to increase interval, or give a calendar month (e.g. 2026-09).")
                             .overrides_with_all(&["year", "week", "day", "from", "to"])
+                            .validator(|value| match stacked_months(&value) {
+                                Some(_) => Ok(()),
+                                None => NamedRange::month(&value).map(|_| ()),
+                            })
+                            .takes_value(true)
+                            .min_values(0)
+                            .max_values(1)
                             .multiple(true),
                     ).arg(
                         Arg::with_name("week")
@@ -102,6 +290,87 @@ fn main() {
                             .help("Date when report should end (e.g. 2018-1-20)")
                             .overrides_with_all(&["year", "month", "week", "day"])
                             .takes_value(true),
+                    ).arg(
+                        Arg::with_name("this-week")
+                            .long("this-week")
+                            .help("Limit report to this week. Weeks start on the first weekday in settings."),
+                    ).arg(
+                        Arg::with_name("last-week")
+                            .long("last-week")
+                            .help("Limit report to last week"),
+                    ).arg(
+                        Arg::with_name("this-month")
+                            .long("this-month")
+                            .help("Limit report to this calendar month"),
+                    ).arg(
+                        Arg::with_name("last-month")
+                            .long("last-month")
+                            .help("Limit report to last calendar month"),
+                    ).arg(
+                        Arg::with_name("quarter")
+                            .long("quarter")
+                            .help("Limit report to a quarter (e.g. 2026-Q3)")
+                            .validator(|value| NamedRange::quarter(&value).map(|_| ()))
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("iso-week")
+                            .long("iso-week")
+                            .help("Limit report to an ISO week, starting on Monday (e.g. 2026-W41)")
+                            .validator(|value| NamedRange::iso_week(&value).map(|_| ()))
+                            .takes_value(true),
+                    ).group(
+                        ArgGroup::with_name("named-range")
+                            .args(&["this-week", "last-week", "this-month", "last-month", "quarter", "iso-week"])
+                            .conflicts_with_all(&["year", "month", "week", "day", "from", "to"]),
+                    ).arg(
+                        Arg::with_name("tag")
+                            .long("tag")
+                            .help("Only include periods with tag. Use multiple to require several tags.")
+                            .takes_value(true)
+                            .number_of_values(1)
+                            .multiple(true),
+                    ).arg(
+                        Arg::with_name("project")
+                            .long("project")
+                            .help("Only include projects matching pattern (e.g. client/*). Use multiple to include several.")
+                            .takes_value(true)
+                            .number_of_values(1)
+                            .multiple(true),
+                    ).arg(
+                        Arg::with_name("depth")
+                            .long("depth")
+                            .help("Number of project levels to show. Deeper projects are rolled up into their parents.")
+                            .validator(is_positive)
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("group-by")
+                            .long("group-by")
+                            .help("Aggregate time per project or per tag")
+                            .possible_values(&["project", "tag"])
+                            .default_value("project")
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("format")
+                            .long("format")
+                            .help("Output format. Machine formats give seconds, the date range and a total.")
+                            .possible_values(&["text", "json", "csv", "markdown"])
+                            .default_value("text")
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("by")
+                            .long("by")
+                            .help("Show a grid with a column per day, week or month, and totals per row and column")
+                            .possible_values(&["day", "week", "month"])
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("split-overnight")
+                            .long("split-overnight")
+                            .help("Count periods running past midnight on each day they cover, instead of the day they started"),
+                    ).arg(
+                        Arg::with_name("billing")
+                            .long("billing")
+                            .help("Show hours, rate and amount per project, with periods tagged +non-billable listed separately. Rates are set with doug settings --rate.")
+                            .conflicts_with_all(&["by", "depth"]),
                     ),
             ).subcommand(
                 SubCommand::with_name("amend")
@@ -111,10 +380,39 @@ fn main() {
                             .help("new project name")
                             .required(true),
                     ),
+            ).subcommand(
+                SubCommand::with_name("rename")
+                    .about("Rename a project across all periods")
+                    .arg(
+                        Arg::with_name("old")
+                            .help("project to rename. Accepts patterns like acme-*")
+                            .required(true),
+                    ).arg(
+                        Arg::with_name("new")
+                            .help("new project name. Each * is replaced with the text matched in the old name (e.g. acme/*)")
+                            .required(true),
+                    ).arg(
+                        Arg::with_name("since")
+                            .long("since")
+                            .help("only rename periods starting on or after date")
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("until")
+                            .long("until")
+                            .help("only rename periods starting on or before date")
+                            .takes_value(true),
+                    ).arg(
+                        Arg::with_name("dryrun")
+                            .long("dry-run")
+                            .help("list periods that would be renamed without changing them"),
+                    ),
             ).subcommand(
                 SubCommand::with_name("edit")
                     .about("Edit last frame or currently running frame")
                     .arg(
+                        Arg::with_name("id")
+                            .help("id of the period to edit (default: last period)"),
+                    ).arg(
                         Arg::with_name("start")
                             .short("s")
                             .long("start")
@@ -126,6 +424,11 @@ fn main() {
                             .long("end")
                             .help("ending date")
                             .takes_value(true),
+                    ).arg(
+                        Arg::with_name("project")
+                            .long("project")
+                            .help("new project name")
+                            .takes_value(true),
                     ),
             )
             .subcommand(
@@ -143,6 +446,66 @@ fn main() {
                     .short("c")
                     .long("clear")
                     .help("clear settings file")
+                ).arg(
+                    Arg::with_name("keep-last")
+                    .long("keep-last")
+                    .takes_value(true)
+                    .validator(is_number)
+                    .help("number of recent backups to keep")
+                ).arg(
+                    Arg::with_name("keep-daily")
+                    .long("keep-daily")
+                    .takes_value(true)
+                    .validator(is_number)
+                    .help("number of days to keep the newest backup of each day for")
+                ).arg(
+                    Arg::with_name("git")
+                    .long("git")
+                    .takes_value(true)
+                    .possible_values(&["on", "off"])
+                    .help("commit the data file to a git repository in the data directory on every change")
+                ).arg(
+                    Arg::with_name("remote")
+                    .long("remote")
+                    .takes_value(true)
+                    .help("git remote to sync the data directory with, e.g. a path to a bare repository")
+                ).arg(
+                    Arg::with_name("key-file")
+                    .long("key-file")
+                    .takes_value(true)
+                    .help("file holding the passphrase for encrypted storage. Without it, DOUG_PASSPHRASE is used. Set it before encrypting, or while DOUG_PASSPHRASE is set.")
+                ).arg(
+                    Arg::with_name("shard-by")
+                    .long("shard-by")
+                    .takes_value(true)
+                    .possible_values(&["year", "month"])
+                    .help("keep a data file per year or per month with json storage")
+                ).arg(
+                    Arg::with_name("first-weekday")
+                    .long("first-weekday")
+                    .takes_value(true)
+                    .possible_values(&["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"])
+                    .help("day that weeks start on in reports")
+                ).arg(
+                    Arg::with_name("rate")
+                    .long("rate")
+                    .takes_value(true)
+                    .number_of_values(1)
+                    .multiple(true)
+                    .validator(is_rate)
+                    .help("hourly rate of a project or +tag for report --billing (e.g. client=120 or +overtime=180). Use multiple to set several.")
+                ).arg(
+                    Arg::with_name("remove-rate")
+                    .long("remove-rate")
+                    .takes_value(true)
+                    .number_of_values(1)
+                    .multiple(true)
+                    .help("project or +tag to remove the hourly rate of")
+                ).arg(
+                    Arg::with_name("currency")
+                    .long("currency")
+                    .takes_value(true)
+                    .help("currency of hourly rates (e.g. EUR)")
                 )
             ).subcommand(
                 SubCommand::with_name("generate-completions")
@@ -162,8 +525,134 @@ fn main() {
                     .about("Delete all intervals for project")
                     .arg(
                         Arg::with_name("project")
-                            .help("new project name")
+                            .help("project to delete. Accepts patterns like client/*")
+                            .required_unless("id"),
+                    ).arg(
+                        Arg::with_name("id")
+                            .long("id")
+                            .help("delete a single period by id")
+                            .conflicts_with("project")
+                            .takes_value(true),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("split")
+                    .about("Split a period in two")
+                    .arg(
+                        Arg::with_name("id")
+                            .help("id of the period to split")
+                            .required(true),
+                    ).arg(
+                        Arg::with_name("at")
+                            .long("at")
+                            .help("date to split the period at (e.g. 14:00)")
+                            .takes_value(true)
                             .required(true),
+                    ).arg(
+                        Arg::with_name("project")
+                            .long("project")
+                            .help("project name for the second half")
+                            .takes_value(true),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("join")
+                    .about("Join two consecutive periods of the same project")
+                    .arg(
+                        Arg::with_name("first")
+                            .help("id of a period to join")
+                            .required(true),
+                    ).arg(
+                        Arg::with_name("second")
+                            .help("id of the other period to join")
+                            .required(true),
+                    ).arg(
+                        Arg::with_name("allow-gap")
+                            .long("allow-gap")
+                            .help("join periods with time between them, counting that time as tracked"),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("check")
+                    .about("Check period data for problems")
+                    .arg(
+                        Arg::with_name("fix")
+                            .long("fix")
+                            .help("repair problems that can be fixed safely. The data file is backed up first."),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("backup")
+                    .about("List, compare and restore backups of the data file")
+                    .settings(&[
+                        AppSettings::SubcommandRequiredElseHelp,
+                        AppSettings::DisableHelpSubcommand,
+                    ])
+                    .subcommand(SubCommand::with_name("list").about("List backups, newest first"))
+                    .subcommand(
+                        SubCommand::with_name("diff")
+                            .about("Show changes since a backup")
+                            .arg(
+                                Arg::with_name("number")
+                                    .help("backup number from backup list")
+                                    .validator(is_positive)
+                                    .required(true),
+                            ),
+                    ).subcommand(
+                        SubCommand::with_name("restore")
+                            .about("Replace the current data with a backup")
+                            .arg(
+                                Arg::with_name("number")
+                                    .help("backup number from backup list")
+                                    .validator(is_positive)
+                                    .required(true),
+                            ),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("undo").about("Revert the last change to periods"),
+            ).subcommand(
+                SubCommand::with_name("redo").about("Reapply the last undone change"),
+            ).subcommand(
+                SubCommand::with_name("compact")
+                    .about("Rewrite the journal with one event per period"),
+            ).subcommand(
+                SubCommand::with_name("encrypt")
+                    .about("Encrypt the data file and backups with a passphrase from DOUG_PASSPHRASE or the key file"),
+            ).subcommand(
+                SubCommand::with_name("decrypt")
+                    .about("Decrypt the data file and backups"),
+            ).subcommand(
+                SubCommand::with_name("migrate-storage")
+                    .about("Move period data to another storage backend")
+                    .arg(
+                        Arg::with_name("to")
+                            .long("to")
+                            .help("storage backend to use from now on")
+                            .possible_values(&["json", "sqlite", "journal"])
+                            .required(true)
+                            .takes_value(true),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("diff")
+                    .about("Show differences between period files")
+                    .arg(
+                        Arg::with_name("file")
+                            .help("period file to compare")
+                            .required(true),
+                    ).arg(
+                        Arg::with_name("other-file")
+                            .help("period file to compare with. Defaults to the current data."),
+                    ).arg(
+                        Arg::with_name("json")
+                            .long("json")
+                            .help("output differences as JSON"),
+                    ),
+            ).subcommand(
+                SubCommand::with_name("sync")
+                    .about("Pull and push period data with the git remote")
+                    .arg(
+                        Arg::with_name("strategy")
+                            .long("strategy")
+                            .help("How to pick between different periods starting at the same time. Running periods end last.")
+                            .possible_values(&["prefer-self", "prefer-other", "earliest-end", "latest-end", "interactive"])
+                            .default_value("earliest-end")
+                            .takes_value(true),
                     ),
             ).subcommand(
                 SubCommand::with_name("merge")
@@ -177,6 +666,13 @@ fn main() {
                             .long("dry-run")
                             .help("don't modify period file")
                             .takes_value(false),
+                    ).arg(
+                        Arg::with_name("strategy")
+                            .long("strategy")
+                            .help("How to pick between different periods starting at the same time. Running periods end last.")
+                            .possible_values(&["prefer-self", "prefer-other", "earliest-end", "latest-end", "interactive"])
+                            .default_value("earliest-end")
+                            .takes_value(true),
                     ),
             );
 
@@ -192,27 +688,71 @@ fn main() {
 
     let results = match matches.subcommand() {
         ("start", Some(matches)) | ("s", Some(matches)) => match matches.value_of("project") {
-            Some(project) => doug.start(project),
+            Some(project) => doug.start(
+                project,
+                &values(matches, "tags"),
+                matches.value_of("message"),
+            ),
             // Restart last project if not argument is provided
             None => doug.restart(),
         },
+        ("add", Some(matches)) => {
+            let (tags, durations): (Vec<&str>, Vec<&str>) = values(matches, "args")
+                .into_iter()
+                .partition(|arg| arg.starts_with('+'));
+            if durations.len() > 1 {
+                Err(format!(
+                    "Expected one duration, found {}",
+                    durations.join(" ")
+                ))
+            } else {
+                doug.add(
+                    matches.value_of("project").unwrap(),
+                    &tags,
+                    &AddOptions {
+                        from: matches.value_of("from"),
+                        to: matches.value_of("to"),
+                        duration: durations.first().cloned(),
+                        on: matches.value_of("on"),
+                        note: matches.value_of("message"),
+                        force: matches.is_present("force"),
+                    },
+                )
+            }
+        }
         ("amend", Some(matches)) => match matches.value_of("project") {
             Some(project) => doug.amend(project),
             None => Err("Missing project name".to_string()),
         },
-        ("delete", Some(matches)) => match matches.value_of("project") {
-            Some(project) => doug.delete(project),
-            None => Err("missing project name".to_string()),
+        ("delete", Some(matches)) => match (matches.value_of("project"), matches.value_of("id")) {
+            (_, Some(id)) => doug.delete_period(id),
+            (Some(project), None) => doug.delete(project),
+            (None, None) => Err("missing project name".to_string()),
         },
         ("status", Some(matches)) => doug.status(matches.is_present("s"), matches.is_present("t")),
-        ("report", Some(matches)) => doug.report(
-            matches.occurrences_of("year") as i32,
-            matches.occurrences_of("month") as i32,
-            matches.occurrences_of("week") as i32,
-            matches.occurrences_of("day") as i32,
-            matches.value_of("from"),
-            matches.value_of("to"),
-        ),
+        ("report", Some(matches)) => doug.report(&ReportOptions {
+            named: named_range(matches),
+            past_years: matches.occurrences_of("year") as i32,
+            past_months: past_months(matches),
+            past_weeks: matches.occurrences_of("week") as i32,
+            past_days: matches.occurrences_of("day") as i32,
+            from_date: matches.value_of("from"),
+            to_date: matches.value_of("to"),
+            tags: values(matches, "tag"),
+            projects: values(matches, "project"),
+            group_by: matches
+                .value_of("group-by")
+                .unwrap_or("project")
+                .parse()
+                .unwrap_or_default(),
+            depth: matches
+                .value_of("depth")
+                .map(|depth| depth.parse().unwrap()),
+            format: matches.value_of("format").unwrap().parse().unwrap(),
+            by: matches.value_of("by").map(|by| by.parse().unwrap()),
+            split_overnight: matches.is_present("split-overnight"),
+            billing: matches.is_present("billing"),
+        }),
         ("generate-completions", Some(matches)) => match matches.value_of("shell") {
             Some("bash") => {
                 cli.gen_completions_to("doug", Shell::Bash, &mut stdout());
@@ -232,16 +772,89 @@ fn main() {
             }
             _ => Err("Invalid option".to_string()),
         },
-        ("edit", Some(matches)) => doug.edit(matches.value_of("start"), matches.value_of("end")),
-        ("stop", Some(_)) => doug.stop(),
+        ("rename", Some(matches)) => doug.rename(
+            matches.value_of("old").unwrap(),
+            matches.value_of("new").unwrap(),
+            matches.value_of("since"),
+            matches.value_of("until"),
+            matches.is_present("dryrun"),
+        ),
+        ("edit", Some(matches)) => doug.edit(
+            matches.value_of("id"),
+            matches.value_of("start"),
+            matches.value_of("end"),
+            matches.value_of("project"),
+        ),
+        ("stop", Some(matches)) => doug.stop(matches.value_of("message")),
+        ("note", Some(matches)) => doug.note(matches.value_of("message").unwrap()),
         ("cancel", Some(_)) => doug.cancel(),
         ("restart", Some(_)) | ("r", Some(_)) => doug.restart(),
-        ("log", Some(_)) => doug.log(),
-        ("settings", Some(matches)) => {
-            doug.settings(matches.value_of("path"), matches.is_present("clear"))
+        ("log", Some(matches)) => doug.log(&LogOptions {
+            grep: matches.value_of("grep"),
+            from_date: matches.value_of("from"),
+            to_date: matches.value_of("to"),
+            split_overnight: matches.is_present("split-overnight"),
+        }),
+        ("settings", Some(matches)) => doug.settings(&SettingsOptions {
+            path: matches.value_of("path"),
+            clear: matches.is_present("clear"),
+            keep_last: number(matches, "keep-last"),
+            keep_daily: number(matches, "keep-daily"),
+            git: matches.value_of("git").map(|git| git == "on"),
+            remote: matches.value_of("remote"),
+            key_file: matches.value_of("key-file"),
+            shard_by: matches
+                .value_of("shard-by")
+                .map(|size| size.parse().unwrap()),
+            first_weekday: matches
+                .value_of("first-weekday")
+                .map(|day| day.parse().unwrap()),
+            rates: values(matches, "rate")
+                .into_iter()
+                .map(|rate| {
+                    let (name, rate) = rate.split_once('=').unwrap();
+                    (name, rate.parse().unwrap())
+                })
+                .collect(),
+            remove_rates: values(matches, "remove-rate"),
+            currency: matches.value_of("currency"),
+        }),
+        ("backup", Some(matches)) => match matches.subcommand() {
+            ("list", Some(_)) => doug.backup_list(),
+            ("diff", Some(matches)) => doug.backup_diff(number(matches, "number").unwrap()),
+            ("restore", Some(matches)) => doug.backup_restore(number(matches, "number").unwrap()),
+            _ => unreachable!(),
+        },
+        ("split", Some(matches)) => doug.split(
+            matches.value_of("id").unwrap(),
+            matches.value_of("at").unwrap(),
+            matches.value_of("project"),
+        ),
+        ("join", Some(matches)) => doug.join(
+            matches.value_of("first").unwrap(),
+            matches.value_of("second").unwrap(),
+            matches.is_present("allow-gap"),
+        ),
+        ("check", Some(matches)) => doug.check(matches.is_present("fix")),
+        ("undo", Some(_)) => doug.undo(),
+        ("redo", Some(_)) => doug.redo(),
+        ("compact", Some(_)) => doug.compact(),
+        ("encrypt", Some(_)) => doug.encrypt(),
+        ("decrypt", Some(_)) => doug.decrypt(),
+        ("migrate-storage", Some(matches)) => {
+            doug.migrate_storage(matches.value_of("to").unwrap().parse().unwrap())
+        }
+        ("diff", Some(matches)) => doug.diff(
+            matches.value_of("file").unwrap(),
+            matches.value_of("other-file"),
+            matches.is_present("json"),
+        ),
+        ("sync", Some(matches)) => {
+            doug.sync(matches.value_of("strategy").unwrap().parse().unwrap())
         }
         ("merge", Some(matches)) => doug.merge(
             matches.value_of("file").unwrap(),
+            matches.value_of("strategy").unwrap().parse().unwrap(),
             matches.is_present("dryrun"),
         ),
         (_, Some(_)) | (_, None) => unreachable!(),
@@ -0,0 +1,239 @@
+//! Merging periods from another data file.
+//!
+//! Periods are matched by their start time, like in [diff](crate::diff). Periods on only one
+//! side are kept, and periods on both sides that differ are a conflict, resolved by a
+//! [Strategy]. Every period from this data file is kept unless a conflict drops it.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use colored::*;
+
+use crate::{diff, Period};
+
+/// How to pick between two different periods with the same start time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Keep the period from this data file
+    PreferSelf,
+    /// Keep the period from the other data file
+    PreferOther,
+    /// Keep the period that ends first. Running periods end last.
+    #[default]
+    EarliestEnd,
+    /// Keep the period that ends last
+    LatestEnd,
+    /// Ask for every conflict
+    Interactive,
+}
+
+impl std::str::FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefer-self" => Ok(Strategy::PreferSelf),
+            "prefer-other" => Ok(Strategy::PreferOther),
+            "earliest-end" => Ok(Strategy::EarliestEnd),
+            "latest-end" => Ok(Strategy::LatestEnd),
+            "interactive" => Ok(Strategy::Interactive),
+            _ => Err(format!("Invalid merge strategy {}", s)),
+        }
+    }
+}
+
+/// Which data file a period came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+/// Two different periods with the same start time, and the one that was kept
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub ours: Period,
+    pub theirs: Period,
+    pub kept: Side,
+}
+
+/// Result of merging two lists of periods
+#[derive(Debug, Default)]
+pub struct Report {
+    /// All periods after merging, sorted by start time
+    pub merged: Vec<Period>,
+    /// Periods that were only in the other data file
+    pub added: Vec<Period>,
+    pub conflicts: Vec<Conflict>,
+    /// Added or conflicting periods that share time with another merged period
+    pub overlaps: Vec<(Period, Period)>,
+}
+
+impl Report {
+    /// Check if merging changes anything
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self
+                .conflicts
+                .iter()
+                .all(|conflict| conflict.kept == Side::Ours)
+    }
+}
+
+/// Merge `theirs` into `ours`.
+///
+/// `choose` is called for every conflict to pick the period to keep. [Strategy::pick]
+/// covers every strategy except [Strategy::Interactive].
+///
+/// # Examples
+/// ```
+/// # extern crate doug;
+/// # extern crate serde_json;
+/// use doug::merge::{merge, Strategy};
+/// use doug::Period;
+///
+/// let periods = |projects: &[&str]| -> Vec<Period> {
+///     let periods = projects.iter().map(|project| {
+///         serde_json::json!({
+///             "project": project,
+///             "start_time": "2026-10-16T09:00:00Z",
+///             "end_time": "2026-10-16T10:00:00Z",
+///         })
+///     });
+///     serde_json::from_value(periods.collect()).unwrap()
+/// };
+/// let ours = periods(&["doug", "email"]);
+/// let theirs = periods(&["doug"]);
+///
+/// let prefer_other = |ours: &Period, theirs: &Period| Ok(Strategy::PreferOther.pick(ours, theirs).unwrap());
+/// let report = merge(&ours, &theirs, prefer_other).unwrap();
+/// assert_eq!(report.merged, ours);
+/// assert!(report.added.is_empty() && report.conflicts.is_empty());
+/// ```
+pub fn merge<F>(ours: &[Period], theirs: &[Period], mut choose: F) -> Result<Report, String>
+where
+    F: FnMut(&Period, &Period) -> Result<Side, String>,
+{
+    let mut by_start: BTreeMap<DateTime<Utc>, (Vec<&Period>, Vec<&Period>)> = BTreeMap::new();
+    for period in ours {
+        by_start
+            .entry(period.start_time)
+            .or_default()
+            .0
+            .push(period);
+    }
+    for period in theirs {
+        by_start
+            .entry(period.start_time)
+            .or_default()
+            .1
+            .push(period);
+    }
+
+    let mut report = Report::default();
+    // whether each merged period came from the other file, and may overlap periods from this one
+    let mut incoming: Vec<bool> = Vec::new();
+    for (ours, theirs) in by_start.values() {
+        for pair in diff::pair(ours, theirs) {
+            match pair {
+                (Some(ours), None) => {
+                    report.merged.push(ours.clone());
+                    incoming.push(false);
+                }
+                (None, Some(theirs)) => {
+                    report.added.push(theirs.clone());
+                    report.merged.push(theirs.clone());
+                    incoming.push(true);
+                }
+                (Some(ours), Some(theirs)) if diff::same(ours, theirs) => {
+                    report.merged.push(ours.clone());
+                    incoming.push(false);
+                }
+                (Some(ours), Some(theirs)) => {
+                    let kept = choose(ours, theirs)?;
+                    match kept {
+                        Side::Ours => report.merged.push(ours.clone()),
+                        Side::Theirs => report.merged.push(theirs.clone()),
+                    }
+                    incoming.push(kept == Side::Theirs);
+                    report.conflicts.push(Conflict {
+                        ours: ours.clone(),
+                        theirs: theirs.clone(),
+                        kept,
+                    });
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    for (i, first) in report.merged.iter().enumerate() {
+        for (j, second) in report.merged.iter().enumerate().skip(i + 1) {
+            if second.start_time >= first.end_or_now() {
+                break;
+            }
+            if (incoming[i] || incoming[j]) && first.overlaps(second) {
+                report.overlaps.push((first.clone(), second.clone()));
+            }
+        }
+    }
+    Ok(report)
+}
+
+impl Strategy {
+    /// Pick between two different periods with the same start time.
+    ///
+    /// Returns `None` for [Strategy::Interactive]. Ties keep the period from this data file.
+    pub fn pick(self, ours: &Period, theirs: &Period) -> Option<Side> {
+        // running periods end after every stopped one
+        let end = |period: &Period| (period.end_time.is_none(), period.end_time);
+        match self {
+            Strategy::PreferSelf => Some(Side::Ours),
+            Strategy::PreferOther => Some(Side::Theirs),
+            Strategy::EarliestEnd if end(theirs) < end(ours) => Some(Side::Theirs),
+            Strategy::LatestEnd if end(theirs) > end(ours) => Some(Side::Theirs),
+            Strategy::EarliestEnd | Strategy::LatestEnd => Some(Side::Ours),
+            Strategy::Interactive => None,
+        }
+    }
+}
+
+/// Describe the changes and conflicts of a merge.
+pub fn render(report: &Report) -> String {
+    let mut message = String::new();
+    if !report.added.is_empty() {
+        message.push_str(format!("Added {} periods:\n", report.added.len()).as_str());
+        for period in &report.added {
+            message.push_str(format!("{} {}\n", "+".green(), period.summary()).as_str());
+        }
+    }
+    if !report.conflicts.is_empty() {
+        message.push_str(format!("{} conflicts:\n", report.conflicts.len()).as_str());
+        for conflict in &report.conflicts {
+            let marker = |side| {
+                if conflict.kept == side {
+                    "kept   ".green()
+                } else {
+                    "dropped".red()
+                }
+            };
+            message.push_str(
+                format!(
+                    "  {} self  {}\n  {} other {}\n",
+                    marker(Side::Ours),
+                    conflict.ours.summary(),
+                    marker(Side::Theirs),
+                    conflict.theirs.summary()
+                )
+                .as_str(),
+            );
+        }
+    }
+    if !report.overlaps.is_empty() {
+        message.push_str(format!("{} overlaps:\n", report.overlaps.len()).as_str());
+        for (first, second) in &report.overlaps {
+            message.push_str(format!("  {}\n  {}\n", first.summary(), second.summary()).as_str());
+        }
+    }
+    message
+}
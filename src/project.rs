@@ -0,0 +1,174 @@
+//! Hierarchical project names.
+//!
+//! Projects use `/` to separate levels, e.g. `client/project/task`.
+
+use std::collections::BTreeMap;
+
+use chrono::Duration;
+
+/// Check if a project name matches a pattern.
+///
+/// `*` matches any run of characters, including `/`. A trailing `/*` also matches the
+/// parent project itself, so `client/*` matches `client`, `client/web` and `client/web/api`.
+/// Patterns without a `*` must match exactly.
+///
+/// # Examples
+/// ```
+/// # extern crate doug;
+/// use doug::project::matches;
+///
+/// assert!(matches("acme/*", "acme"));
+/// assert!(matches("acme/*", "acme/web/api"));
+/// assert!(matches("acme-*", "acme-web"));
+/// assert!(!matches("acme/*", "acme-web"));
+/// assert!(!matches("acme", "acme/web"));
+/// ```
+pub fn matches(pattern: &str, project: &str) -> bool {
+    if let Some(parent) = pattern.strip_suffix("/*") {
+        if !parent.contains('*') && parent == project {
+            return true;
+        }
+    }
+    captures(pattern, project).is_some()
+}
+
+/// Rename a project matching `pattern`, substituting the text matched by each `*` in
+/// `pattern` into the `*`s of `replacement`, in order.
+///
+/// Returns `None` if the project doesn't match, or if `replacement` has more `*`s than
+/// `pattern`.
+///
+/// # Examples
+/// ```
+/// # extern crate doug;
+/// use doug::project::rename;
+///
+/// assert_eq!(rename("acme-*", "acme/*", "acme-web"), Some("acme/web".to_string()));
+/// assert_eq!(rename("acme/*", "client/*", "acme"), Some("client".to_string()));
+/// assert_eq!(rename("old", "new", "old"), Some("new".to_string()));
+/// assert_eq!(rename("old", "new", "older"), None);
+/// ```
+pub fn rename(pattern: &str, replacement: &str, project: &str) -> Option<String> {
+    if let (Some(parent), Some(new_parent)) =
+        (pattern.strip_suffix("/*"), replacement.strip_suffix("/*"))
+    {
+        if !parent.contains('*') && parent == project {
+            return Some(new_parent.to_string());
+        }
+    }
+    let captured = captures(pattern, project)?;
+    let mut parts = replacement.split('*');
+    let mut renamed = parts.next().unwrap_or_default().to_string();
+    let mut captured = captured.into_iter();
+    for part in parts {
+        renamed.push_str(captured.next()?);
+        renamed.push_str(part);
+    }
+    Some(renamed)
+}
+
+/// Match a project name against a pattern, returning the text matched by each `*`.
+pub fn captures<'a>(pattern: &str, project: &'a str) -> Option<Vec<&'a str>> {
+    let mut captured = Vec::new();
+    if capture(pattern, project, 0, &mut captured) {
+        Some(captured)
+    } else {
+        None
+    }
+}
+
+fn capture<'a>(pattern: &str, text: &'a str, offset: usize, captured: &mut Vec<&'a str>) -> bool {
+    let rest = &text[offset..];
+    match pattern.find('*') {
+        None => pattern == rest,
+        Some(star) => {
+            let (literal, pattern) = (&pattern[..star], &pattern[star + 1..]);
+            if !rest.starts_with(literal) {
+                return false;
+            }
+            let start = offset + literal.len();
+            // try the longest match first so `a*` captures as much as possible
+            let mut ends: Vec<usize> = text[start..]
+                .char_indices()
+                .map(|(i, _)| start + i)
+                .collect();
+            ends.push(text.len());
+            for end in ends.into_iter().rev() {
+                captured.push(&text[start..end]);
+                if capture(pattern, text, end, captured) {
+                    return true;
+                }
+                captured.pop();
+            }
+            false
+        }
+    }
+}
+
+/// Tracked time of a project and its subprojects.
+///
+/// The duration of every node includes the time of all of its children.
+#[derive(Debug, Clone)]
+pub struct Tree {
+    pub duration: Duration,
+    pub children: BTreeMap<String, Tree>,
+}
+
+impl Default for Tree {
+    fn default() -> Self {
+        Tree {
+            duration: Duration::zero(),
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+impl Tree {
+    /// Add time to a project and every one of its parents.
+    pub fn insert(&mut self, project: &str, duration: Duration) {
+        self.duration = self.duration + duration;
+        let mut node = self;
+        for name in project.split('/') {
+            node = node.children.entry(name.to_string()).or_default();
+            node.duration = node.duration + duration;
+        }
+    }
+
+    /// Flatten the tree into rows of `(level, name, duration, own duration)`, parents before
+    /// children.
+    ///
+    /// The own duration is the time not in any row below, so the own durations of all rows add
+    /// up to the time of the tree.
+    ///
+    /// # Arguments
+    /// * `depth` — number of levels to show. Deeper projects are rolled up into their parents.
+    pub fn rows(&self, depth: Option<usize>) -> Vec<(usize, &str, Duration, Duration)> {
+        let mut rows = Vec::new();
+        self.push_rows(0, depth, &mut rows);
+        rows
+    }
+
+    fn push_rows<'a>(
+        &'a self,
+        level: usize,
+        depth: Option<usize>,
+        rows: &mut Vec<(usize, &'a str, Duration, Duration)>,
+    ) {
+        if depth.is_some_and(|depth| level >= depth) {
+            return;
+        }
+        let children_shown = depth.is_none_or(|depth| level + 1 < depth);
+        for (name, child) in &self.children {
+            let own = if children_shown {
+                child
+                    .children
+                    .values()
+                    .fold(child.duration, |own, grandchild| own - grandchild.duration)
+            } else {
+                child.duration
+            };
+            rows.push((level, name, child.duration, own));
+            child.push_rows(level + 1, depth, rows);
+        }
+    }
+}
@@ -0,0 +1,525 @@
+//! Output formats of [Doug::report](crate::Doug::report).
+//!
+//! A [Report] holds the aggregated time of each project or tag. It's rendered as aligned,
+//! colored text for the terminal, or as JSON, CSV or Markdown for scripts and wiki pages. The
+//! machine formats give durations in seconds, the date range of the report, and a total.
+//!
+//! With a [Bucket], the report is a grid: the time of every row is also split into days, weeks
+//! or months, with a total for every column.
+
+use chrono::{Date, Duration, Local, Weekday};
+use colored::*;
+use serde_json::{json, Value};
+
+use crate::{calendar, format, GroupBy};
+
+/// How to render a [Report]
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "markdown" => Ok(Format::Markdown),
+            _ => Err(format!("Invalid report format {}", s)),
+        }
+    }
+}
+
+/// Columns of a grid report
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Bucket {
+    Day,
+    /// Weeks starting on [Settings::first_weekday](crate::settings::Settings::first_weekday)
+    Week,
+    Month,
+}
+
+impl std::str::FromStr for Bucket {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Bucket::Day),
+            "week" => Ok(Bucket::Week),
+            "month" => Ok(Bucket::Month),
+            _ => Err(format!("Invalid bucket {}", s)),
+        }
+    }
+}
+
+impl Bucket {
+    /// First day of the bucket holding `date`, with weeks starting on `first_weekday`
+    pub fn start(self, date: Date<Local>, first_weekday: Weekday) -> Date<Local> {
+        match self {
+            Bucket::Day => date,
+            Bucket::Week => calendar::week_start(date, first_weekday),
+            Bucket::Month => calendar::month_start(date),
+        }
+    }
+
+    /// First day of the bucket after the one starting at `start`
+    fn next(self, start: Date<Local>) -> Date<Local> {
+        match self {
+            Bucket::Day => start + Duration::days(1),
+            Bucket::Week => start + Duration::weeks(1),
+            Bucket::Month => calendar::add_months(start, 1),
+        }
+    }
+
+    /// Starts of the buckets from the one holding `from` to the one holding `to`
+    ///
+    /// # Examples
+    /// ```
+    /// # extern crate chrono;
+    /// # extern crate doug;
+    /// use chrono::{Local, TimeZone, Weekday};
+    /// use doug::report::Bucket;
+    ///
+    /// let (from, to) = (Local.ymd(2026, 10, 1), Local.ymd(2026, 10, 16));
+    /// let columns = Bucket::Week.columns(from, to, Weekday::Mon);
+    /// assert_eq!(
+    ///     columns,
+    ///     vec![Local.ymd(2026, 9, 28), Local.ymd(2026, 10, 5), Local.ymd(2026, 10, 12)]
+    /// );
+    /// ```
+    pub fn columns(
+        self,
+        from: Date<Local>,
+        to: Date<Local>,
+        first_weekday: Weekday,
+    ) -> Vec<Date<Local>> {
+        let mut columns = Vec::new();
+        let mut start = self.start(from, first_weekday);
+        while start <= to {
+            columns.push(start);
+            start = self.next(start);
+        }
+        columns
+    }
+
+    /// Column heading for the bucket starting at `start`, e.g. `2026-10-12` or `2026-10`
+    pub fn label(self, start: Date<Local>) -> String {
+        match self {
+            Bucket::Day | Bucket::Week => start.format("%F").to_string(),
+            Bucket::Month => start.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// Time spent on a project or tag
+#[derive(Debug, Clone)]
+pub struct Row {
+    /// Full project name (e.g. `client/web`), or tag with its `+`
+    pub name: String,
+    /// Nesting of the project, 0 for top level projects and tags
+    pub level: usize,
+    /// Time of the row, including the time of rows below it
+    pub duration: Duration,
+    /// Time not in any row below. Grouped by project, the own time of all rows adds up to the
+    /// total.
+    pub own: Duration,
+    /// Time in each column of a grid report, empty otherwise. Includes the rows below.
+    pub buckets: Vec<Duration>,
+}
+
+impl Row {
+    /// Check if the row rolls up the time of rows below it
+    pub fn is_rollup(&self) -> bool {
+        self.own != self.duration
+    }
+}
+
+/// Aggregated time for a date range
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// First day of the range. Without a start date, the day of the earliest period.
+    pub from: Date<Local>,
+    /// Last day of the range
+    pub to: Date<Local>,
+    /// Day of the earliest period in the report
+    pub first: Date<Local>,
+    pub group_by: GroupBy,
+    /// Parents before their children
+    pub rows: Vec<Row>,
+    /// Time of all periods in the report. Periods with several tags are counted once.
+    pub total: Duration,
+    /// Size of the columns of a grid report
+    pub by: Option<Bucket>,
+    /// Start of each column of a grid report
+    pub columns: Vec<Date<Local>>,
+    /// Time in each column, counting periods with several tags once
+    pub column_totals: Vec<Duration>,
+}
+
+impl Report {
+    fn labels(&self) -> Vec<String> {
+        match self.by {
+            Some(by) => self.columns.iter().map(|start| by.label(*start)).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Render a report in `format`.
+///
+/// # Examples
+/// ```
+/// # extern crate chrono;
+/// # extern crate doug;
+/// use chrono::{Duration, Local, TimeZone};
+/// use doug::report::{render, Format, Report, Row};
+/// use doug::GroupBy;
+///
+/// let report = Report {
+///     from: Local.ymd(2026, 10, 12),
+///     to: Local.ymd(2026, 10, 16),
+///     first: Local.ymd(2026, 10, 12),
+///     group_by: GroupBy::Project,
+///     rows: vec![Row {
+///         name: "client".to_string(),
+///         level: 0,
+///         duration: Duration::hours(2),
+///         own: Duration::hours(2),
+///         buckets: Vec::new(),
+///     }],
+///     total: Duration::hours(2),
+///     by: None,
+///     columns: Vec::new(),
+///     column_totals: Vec::new(),
+/// };
+/// assert_eq!(
+///     render(&report, Format::Csv).unwrap(),
+///     "row_type,project,level,rollup,seconds,own_seconds,from,to\n\
+///      project,client,0,false,7200,7200,2026-10-12,2026-10-16\n\
+///      total,,,,7200,7200,2026-10-12,2026-10-16\n"
+/// );
+/// ```
+pub fn render(report: &Report, format: Format) -> Result<String, String> {
+    match format {
+        Format::Text => Ok(text(report)),
+        Format::Json => serde_json::to_string_pretty(&json(report))
+            .map(|json| json + "\n")
+            .map_err(|_| "Couldn't serialize data to string".to_string()),
+        Format::Csv => Ok(csv(report)),
+        Format::Markdown => Ok(markdown(report)),
+    }
+}
+
+fn seconds(durations: &[Duration]) -> Vec<i64> {
+    durations.iter().map(Duration::num_seconds).collect()
+}
+
+fn json(report: &Report) -> Value {
+    let mut json = json!({
+        "from": report.from.format("%F").to_string(),
+        "to": report.to.format("%F").to_string(),
+        "group_by": group_name(report.group_by),
+        "rows": report.rows.iter().map(|row| {
+            let mut json = json!({
+                group_name(report.group_by): row.name,
+                "level": row.level,
+                "rollup": row.is_rollup(),
+                "seconds": row.duration.num_seconds(),
+                "own_seconds": row.own.num_seconds(),
+            });
+            if report.by.is_some() {
+                json["buckets"] = seconds(&row.buckets).into();
+            }
+            json
+        }).collect::<Vec<_>>(),
+        "total": { "seconds": report.total.num_seconds() },
+    });
+    if let Some(by) = report.by {
+        json["by"] = format!("{:?}", by).to_lowercase().into();
+        json["columns"] = report.labels().into();
+        json["total"]["buckets"] = seconds(&report.column_totals).into();
+    }
+    json
+}
+
+fn group_name(group_by: GroupBy) -> &'static str {
+    match group_by {
+        GroupBy::Project => "project",
+        GroupBy::Tag => "tag",
+    }
+}
+
+/// Space-aligned rows with projects indented under their parents
+fn text(report: &Report) -> String {
+    if report.by.is_some() {
+        return text_grid(report);
+    }
+    let names = indented_names(report);
+    // find lengths of names and durations for alignment
+    let max_proj_len = names.iter().map(String::len).max().unwrap_or(0);
+    let max_diff_len = report
+        .rows
+        .iter()
+        .map(|row| format::duration(row.duration).len())
+        .max()
+        .unwrap_or(0);
+    let mut message = format!(
+        "{start} -> {end}\n",
+        start = report.first.format("%A %-d %B %Y").to_string().blue(),
+        end = report.to.format("%A %-d %B %Y").to_string().blue()
+    );
+    for (name, row) in names.iter().zip(&report.rows) {
+        message.push_str(
+            format!(
+                "{project:pwidth$} {duration:>dwidth$}\n",
+                project = name.green(),
+                duration = format::duration(row.duration).bold(),
+                pwidth = max_proj_len,
+                dwidth = max_diff_len
+            )
+            .as_str(),
+        );
+    }
+    message
+}
+
+/// Names indented under their parents, for text output
+fn indented_names(report: &Report) -> Vec<String> {
+    report
+        .rows
+        .iter()
+        .map(|row| {
+            let name = match report.group_by {
+                GroupBy::Project => row.name.rsplit('/').next().unwrap_or(&row.name),
+                GroupBy::Tag => &row.name,
+            };
+            format!("{}{}", "  ".repeat(row.level), name)
+        })
+        .collect()
+}
+
+/// A cell of a text grid, `-` for no time
+fn grid_cell(duration: Duration) -> String {
+    if duration == Duration::zero() {
+        "-".to_string()
+    } else {
+        format::duration(duration)
+    }
+}
+
+/// A row per project or tag and a column per bucket, with totals in the last row and column
+fn text_grid(report: &Report) -> String {
+    let mut names = indented_names(report);
+    names.push("Total".to_string());
+    let mut lines: Vec<Vec<String>> = report
+        .rows
+        .iter()
+        .map(|row| {
+            let mut cells: Vec<String> = row.buckets.iter().map(|d| grid_cell(*d)).collect();
+            cells.push(format::duration(row.duration));
+            cells
+        })
+        .collect();
+    let mut totals: Vec<String> = report.column_totals.iter().map(|d| grid_cell(*d)).collect();
+    totals.push(format::duration(report.total));
+    lines.push(totals);
+    let mut headings = report.labels();
+    headings.push("Total".to_string());
+
+    let name_width = names.iter().map(String::len).max().unwrap_or(0);
+    let widths: Vec<usize> = headings
+        .iter()
+        .enumerate()
+        .map(|(column, heading)| {
+            lines
+                .iter()
+                .map(|cells| cells[column].len())
+                .chain(std::iter::once(heading.len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut message = format!(
+        "{start} -> {end}\n",
+        start = report.from.format("%A %-d %B %Y").to_string().blue(),
+        end = report.to.format("%A %-d %B %Y").to_string().blue()
+    );
+    message.push_str(&" ".repeat(name_width));
+    for (heading, width) in headings.iter().zip(&widths) {
+        message.push_str(&format!("  {:>width$}", heading.blue(), width = width));
+    }
+    message.push('\n');
+    let last = names.len() - 1;
+    for (index, (name, cells)) in names.iter().zip(&lines).enumerate() {
+        let name = if index == last {
+            name.bold()
+        } else {
+            name.green()
+        };
+        message.push_str(&format!("{:width$}", name, width = name_width));
+        for (cell, width) in cells.iter().zip(&widths) {
+            message.push_str(&format!("  {:>width$}", cell, width = width));
+        }
+        message.push('\n');
+    }
+    message
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break.
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Seconds of each bucket, as CSV fields following a comma
+fn csv_buckets(buckets: &[Duration]) -> String {
+    buckets
+        .iter()
+        .map(|duration| format!("{},", duration.num_seconds()))
+        .collect()
+}
+
+/// One line per row and a total line, each with the range of the report. The `row_type`
+/// column is `project` or `tag` for rows, and `total` for the total line, which has no name.
+/// Grid reports have a column of seconds per bucket before the total seconds.
+///
+/// Roll-up rows include the time of the rows below them. Grouped by project, summing
+/// `own_seconds`, or `seconds` of the rows that aren't roll-ups, gives the total. Grouped by
+/// tag, periods with several tags are in several rows but counted once in the total.
+fn csv(report: &Report) -> String {
+    let from = report.from.format("%F");
+    let to = report.to.format("%F");
+    let columns: String = report
+        .labels()
+        .iter()
+        .map(|label| format!("{},", label))
+        .collect();
+    let mut csv = format!(
+        "row_type,{},level,rollup,{}seconds,own_seconds,from,to\n",
+        group_name(report.group_by),
+        columns
+    );
+    for row in &report.rows {
+        csv.push_str(&format!(
+            "{},{},{},{},{}{},{},{},{}\n",
+            group_name(report.group_by),
+            csv_field(&row.name),
+            row.level,
+            row.is_rollup(),
+            csv_buckets(&row.buckets),
+            row.duration.num_seconds(),
+            row.own.num_seconds(),
+            from,
+            to
+        ));
+    }
+    csv.push_str(&format!(
+        "total,,,,{}{},{},{},{}\n",
+        csv_buckets(&report.column_totals),
+        report.total.num_seconds(),
+        report.total.num_seconds(),
+        from,
+        to
+    ));
+    csv
+}
+
+/// Escape characters that would break a Markdown table cell.
+pub(crate) fn markdown_cell(cell: &str) -> String {
+    cell.replace('|', "\\|")
+}
+
+/// Name cell of a Markdown table, in italics for roll-up rows
+fn markdown_name(row: &Row) -> String {
+    if row.is_rollup() {
+        format!("*{}*", markdown_cell(&row.name))
+    } else {
+        markdown_cell(&row.name)
+    }
+}
+
+/// A line explaining italic rows, if there are any
+fn markdown_rollup_note(report: &Report) -> &'static str {
+    if report.rows.iter().any(Row::is_rollup) {
+        "\n*Italic rows include the time of the rows below them.*\n"
+    } else {
+        ""
+    }
+}
+
+/// A table with a bold total row, below a line with the range of the report. Grid reports
+/// have a column per bucket, and a total column instead of seconds.
+fn markdown(report: &Report) -> String {
+    let heading = match report.group_by {
+        GroupBy::Project => "Project",
+        GroupBy::Tag => "Tag",
+    };
+    let labels = report.labels();
+    let mut markdown = format!(
+        "{} to {}\n\n",
+        report.from.format("%F"),
+        report.to.format("%F")
+    );
+    if report.by.is_some() {
+        markdown.push_str(&format!(
+            "| {} | {} | Total |\n",
+            heading,
+            labels.join(" | ")
+        ));
+        markdown.push_str(&format!(
+            "| --- |{} ---: |\n",
+            " ---: |".repeat(labels.len())
+        ));
+        let cells = |buckets: &[Duration], emphasis: &str| -> String {
+            buckets
+                .iter()
+                .map(|duration| format!(" {0}{1}{0} |", emphasis, format::duration(*duration)))
+                .collect()
+        };
+        for row in &report.rows {
+            markdown.push_str(&format!(
+                "| {} |{} {} |\n",
+                markdown_name(row),
+                cells(&row.buckets, ""),
+                format::duration(row.duration)
+            ));
+        }
+        markdown.push_str(&format!(
+            "| **Total** |{} **{}** |\n",
+            cells(&report.column_totals, "**"),
+            format::duration(report.total)
+        ));
+        markdown.push_str(markdown_rollup_note(report));
+        return markdown;
+    }
+    markdown.push_str(&format!(
+        "| {} | Time | Seconds |\n| --- | ---: | ---: |\n",
+        heading
+    ));
+    for row in &report.rows {
+        markdown.push_str(&format!(
+            "| {} | {} | {} |\n",
+            markdown_name(row),
+            format::duration(row.duration),
+            row.duration.num_seconds()
+        ));
+    }
+    markdown.push_str(&format!(
+        "| **Total** | **{}** | **{}** |\n",
+        format::duration(report.total),
+        report.total.num_seconds()
+    ));
+    markdown.push_str(markdown_rollup_note(report));
+    markdown
+}
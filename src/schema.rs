@@ -0,0 +1,123 @@
+//! Layout of the period data file.
+//!
+//! The data file is a versioned envelope, `{"version": 2, "periods": [...]}`.
+//! Older layouts are detected when reading and migrated in memory, one version at a time.
+//!
+//! | version | layout                                 |
+//! |---------|----------------------------------------|
+//! | 1       | bare array of periods                  |
+//! | 2       | `{"version": 2, "periods": [...]}`     |
+
+use std::io::Read;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::Period;
+
+/// Current version of the data file layout
+pub const VERSION: u64 = 2;
+
+#[derive(Serialize)]
+struct DataFile<'a> {
+    version: u64,
+    periods: &'a [Period],
+}
+
+/// Periods read from a data file.
+pub struct Loaded {
+    pub periods: Vec<Period>,
+    /// Version of the file, if it was migrated from an older layout.
+    pub migrated_from: Option<u64>,
+}
+
+/// Read periods from a data file, migrating older layouts.
+///
+/// An empty file is treated as a new data file without any periods.
+///
+/// # Examples
+/// ```
+/// # extern crate doug;
+/// use doug::schema;
+///
+/// let bare = r#"[{"project":"doug","start_time":"2026-10-16T09:00:00Z","end_time":null}]"#;
+/// let loaded = schema::read(bare.as_bytes()).unwrap();
+/// assert_eq!(loaded.migrated_from, Some(1));
+/// assert_eq!(loaded.periods.len(), 1);
+///
+/// let current = schema::to_string(&loaded.periods).unwrap();
+/// assert!(current.starts_with(r#"{"version":2,"#));
+/// let reloaded = schema::read(current.as_bytes()).unwrap();
+/// assert_eq!(reloaded.migrated_from, None);
+/// assert_eq!(reloaded.periods, loaded.periods);
+///
+/// assert!(schema::read(r#"{"version":3,"periods":[]}"#.as_bytes()).is_err());
+/// assert!(schema::read("".as_bytes()).unwrap().periods.is_empty());
+/// ```
+pub fn read<R: Read>(reader: R) -> Result<Loaded, String> {
+    let value: Value = match serde_json::from_reader(reader) {
+        Ok(value) => value,
+        Err(ref error) if error.is_eof() => {
+            return Ok(Loaded {
+                periods: Vec::new(),
+                migrated_from: None,
+            });
+        }
+        Err(error) => return Err(format!("There was a serialization issue: {:?}\n", error)),
+    };
+
+    let original = version(&value)?;
+    if original > VERSION {
+        return Err(format!(
+            "Data file has version {}, but this doug only supports up to version {}. Please upgrade doug.\n",
+            original, VERSION
+        ));
+    }
+    let mut value = value;
+    let mut current = original;
+    while current < VERSION {
+        value = migrate(current, value);
+        current += 1;
+    }
+
+    let periods = serde_json::from_value(value["periods"].take())
+        .map_err(|error| format!("There was a serialization issue: {:?}\n", error))?;
+    Ok(Loaded {
+        periods,
+        migrated_from: if original < VERSION {
+            Some(original)
+        } else {
+            None
+        },
+    })
+}
+
+/// Serialize periods using the current layout.
+pub fn to_string(periods: &[Period]) -> Result<String, String> {
+    serde_json::to_string(&DataFile {
+        version: VERSION,
+        periods,
+    })
+    .map_err(|_| "Couldn't serialize data to string".to_string())
+}
+
+/// Detect the layout version of a data file
+fn version(value: &Value) -> Result<u64, String> {
+    match value {
+        Value::Array(_) => Ok(1),
+        Value::Object(object) => object
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| "Data file is missing a valid version field.\n".to_string()),
+        _ => Err("Data file has an unknown format.\n".to_string()),
+    }
+}
+
+/// Migrate data from `version` to `version + 1`
+fn migrate(version: u64, value: Value) -> Value {
+    match version {
+        // wrap the bare array in an envelope
+        1 => json!({ "version": 2, "periods": value }),
+        _ => unreachable!("no migration from version {}", version),
+    }
+}
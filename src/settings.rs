@@ -1,30 +1,86 @@
+use chrono::Weekday;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use serde_json::Error;
 use std::fs::{DirBuilder, OpenOptions};
-use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::billing::Rates;
+use crate::file;
+use crate::storage::{ShardSize, StorageKind};
 
 /// Doug settings that are stored on disk
 #[derive(Eq, PartialEq, Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Settings {
     /// Specify default location for data file
     pub data_location: PathBuf,
+    /// How many backups of the data file to keep
+    #[serde(default)]
+    pub backups: Retention,
+    /// Backend used to store periods
+    #[serde(default)]
+    pub storage: StorageKind,
+    /// Whether json storage keeps a file per year or per month
+    #[serde(default)]
+    pub shard_by: ShardSize,
+    /// Commit the data file to a git repository in the data directory on every save
+    #[serde(default)]
+    pub git: bool,
+    /// Git remote to sync the data directory with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// File holding the passphrase for encrypted storage. Without it, the passphrase is read
+    /// from `DOUG_PASSPHRASE`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+    /// Day that weeks start on in reports, Monday if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_weekday: Option<Weekday>,
+    /// Hourly rates for `doug report --billing`
+    #[serde(default, skip_serializing_if = "Rates::is_empty")]
+    pub rates: Rates,
+}
+
+/// Retention policy for backups of the data file.
+///
+/// A backup is kept if either rule applies to it. The newest backup is always kept.
+#[derive(Eq, PartialEq, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Retention {
+    /// Keep this many of the most recent backups
+    pub keep_last: usize,
+    /// Keep the newest backup of each of this many most recent days
+    pub keep_daily: usize,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            keep_last: 50,
+            keep_daily: 0,
+        }
+    }
 }
 
 impl Settings {
+    /// Day that weeks start on in reports
+    pub fn first_weekday(&self) -> Weekday {
+        self.first_weekday.unwrap_or(Weekday::Mon)
+    }
+
     /// Load settings.
     /// If the settings file doesn't exist, it will be created.
-    pub fn new(folder: &PathBuf) -> Result<Self, String> {
+    pub fn new(folder: &Path) -> Result<Self, String> {
         DirBuilder::new()
             .recursive(true)
-            .create(&folder)
+            .create(folder)
             .map_err(|err| format!("Couldn't create data directory: {:?}\n", err))?;
 
         // create settings file
-        let location = folder.as_path().join("settings.json");
+        let location = folder.join("settings.json");
         let data_file = OpenOptions::new()
             .create(true)
+            .truncate(false)
             .read(true)
             .write(true)
             .open(&location)
@@ -39,6 +95,7 @@ impl Settings {
             Err(ref error) if error.is_eof() => {
                 let settings = Settings {
                     data_location: folder.to_path_buf(),
+                    ..Settings::default()
                 };
                 Settings::save(&settings, folder)?;
                 Ok(settings)
@@ -47,30 +104,20 @@ impl Settings {
         }
     }
 
-    pub fn save(&self, folder: &PathBuf) -> Result<(), String> {
-        let mut data_file = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(&folder.join("settings.json"))
-            .map_err(|err| format!("Couldn't open settings file: {:?}\n", err))?;
-
+    pub fn save(&self, folder: &Path) -> Result<(), String> {
         let serialized = serde_json::to_string(&self)
             .map_err(|_| "Couldn't serialize data to string".to_string())?;
 
-        data_file
-            .write_all(serialized.as_bytes())
-            .map_err(|_| "Couldn't write serialized data to file".to_string())?;
-        Ok(())
+        file::write_atomic(&folder.join("settings.json"), serialized.as_bytes())
     }
 
-    pub fn clear(&mut self, folder: &PathBuf) -> Result<(), String> {
+    pub fn clear(&mut self, folder: &Path) -> Result<(), String> {
         OpenOptions::new()
             .create(true)
             .read(true)
             .write(true)
             .truncate(true)
-            .open(&folder.join("settings.json"))
+            .open(folder.join("settings.json"))
             .map_err(|err| format!("Couldn't clear settings file: {:?}\n", err))?;
         Ok(())
     }
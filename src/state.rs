@@ -0,0 +1,105 @@
+//! A small sidecar file with the last period, so frequent commands don't load any periods.
+//!
+//! Prompts and status bars run `doug status` every few seconds, and it only needs the last
+//! period. So do `start`, `stop`, `cancel` and `amend`. Every save writes the last period to
+//! `state.json` in the data directory, along with the size and modification time of the data
+//! files it came from. If those files changed since, e.g. after `doug edit` or a git sync, the
+//! state is ignored and rebuilt from the periods.
+//!
+//! How much the state saves depends on the storage backend:
+//!
+//! * `status` doesn't load periods at all, except with encrypted storage
+//! * `start`, `stop`, `cancel` and `amend` load just the shard holding the last period with
+//!   [ShardedStorage](crate::storage::ShardedStorage), and just the last period with
+//!   [SqliteStorage](crate::storage::SqliteStorage)
+//! * [JournalStorage](crate::journal::JournalStorage) has to replay the whole journal to find
+//!   the last period, so those commands load all periods
+//!
+//! No state is kept for encrypted storage, since it would hold a period in plain text. Every
+//! command decrypts the whole data file.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{file, Period};
+
+/// Size and modification time of a data file, to notice when it changes
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct Stamp {
+    location: PathBuf,
+    /// `None` if the file doesn't exist
+    modified: Option<DateTime<Utc>>,
+    len: u64,
+}
+
+impl Stamp {
+    fn new(location: &Path) -> Self {
+        let metadata = fs::metadata(location).ok();
+        Stamp {
+            location: location.to_path_buf(),
+            modified: metadata
+                .as_ref()
+                .and_then(|metadata| metadata.modified().ok())
+                .map(DateTime::<Utc>::from),
+            len: metadata.map_or(0, |metadata| metadata.len()),
+        }
+    }
+}
+
+/// The last period of a data directory
+#[derive(Serialize, Deserialize, Debug)]
+pub struct State {
+    /// The period that started last, `None` if there are no periods
+    pub last: Option<Period>,
+    stamps: Vec<Stamp>,
+}
+
+impl State {
+    /// Record the last period, and the current state of the data files it was loaded from.
+    pub fn new(last: Option<Period>, files: &[PathBuf]) -> Self {
+        State {
+            last,
+            stamps: files.iter().map(|file| Stamp::new(file)).collect(),
+        }
+    }
+
+    /// Load the state of a data directory.
+    ///
+    /// Returns `None` if there is no state, or if the data files changed since it was saved.
+    pub fn load(folder: &Path) -> Option<Self> {
+        let contents = fs::read(location(folder)).ok()?;
+        let state: State = serde_json::from_slice(&contents).ok()?;
+        if state
+            .stamps
+            .iter()
+            .all(|stamp| *stamp == Stamp::new(&stamp.location))
+        {
+            Some(state)
+        } else {
+            None
+        }
+    }
+
+    pub fn save(&self, folder: &Path) -> Result<(), String> {
+        let serialized = serde_json::to_vec(self)
+            .map_err(|_| "Couldn't serialize data to string".to_string())?;
+        file::write_atomic(&location(folder), &serialized)
+    }
+
+    /// Remove the state of a data directory, if there is one.
+    pub fn remove(folder: &Path) -> Result<(), String> {
+        let location = location(folder);
+        if location.exists() {
+            fs::remove_file(&location)
+                .map_err(|err| format!("Couldn't remove {:?}: {:?}", location, err))?;
+        }
+        Ok(())
+    }
+}
+
+fn location(folder: &Path) -> PathBuf {
+    folder.join("state.json")
+}
@@ -0,0 +1,827 @@
+//! Storage backends for period data.
+//!
+//! [Doug](crate::Doug) loads the periods it needs when it starts and hands the list back when
+//! saving. A backend decides how that list is kept on disk:
+//!
+//! * [ShardedStorage] — a `periods` directory with a JSON file per year or month, where only
+//!   the files a command needs are read and only changed files are rewritten (the default)
+//! * [JsonStorage] — a single JSON file, used for backups and the old `periods.json` layout
+//! * [SqliteStorage] — a `periods.sqlite` database, where only the periods a command needs
+//!   are read and only changed periods are written
+//! * [JournalStorage] — a `periods.journal` file that changes are appended to, see [journal]
+//! * [EncryptedStorage] — a `periods.enc` file, like `periods.json` but encrypted, see [crypto]
+//!
+//! The backend is selected with [settings::Settings::storage].
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Utc};
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::journal::JournalStorage;
+use crate::{crypto, file, schema, settings, Period};
+
+/// Available storage backends
+#[derive(Eq, PartialEq, Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    #[default]
+    Json,
+    Sqlite,
+    Journal,
+    Encrypted,
+}
+
+impl StorageKind {
+    /// Name of the data file in the data directory
+    pub fn file_name(self) -> &'static str {
+        match self {
+            StorageKind::Json => "periods",
+            StorageKind::Sqlite => "periods.sqlite",
+            StorageKind::Journal => "periods.journal",
+            StorageKind::Encrypted => "periods.enc",
+        }
+    }
+}
+
+impl std::str::FromStr for StorageKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(StorageKind::Json),
+            "sqlite" => Ok(StorageKind::Sqlite),
+            "journal" => Ok(StorageKind::Journal),
+            "encrypted" => Ok(StorageKind::Encrypted),
+            _ => Err(format!("Invalid storage {}", s)),
+        }
+    }
+}
+
+/// Periods a command needs, so [ShardedStorage] and [SqliteStorage] can skip the rest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Range {
+    /// The current shard, and earlier shards up to the one holding the last period
+    Latest,
+    /// Periods starting between two times. `None` is unbounded.
+    Between(Option<DateTime<Utc>>, Option<DateTime<Utc>>),
+    /// Periods that share time with the range between two times, including periods that
+    /// started before it and run into it. `None` is unbounded.
+    Overlapping(Option<DateTime<Utc>>, Option<DateTime<Utc>>),
+    All,
+}
+
+/// Size of the files of [ShardedStorage]
+#[derive(Eq, PartialEq, Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ShardSize {
+    #[default]
+    Year,
+    Month,
+}
+
+impl ShardSize {
+    /// Name of the shard holding periods that start at `time`, e.g. `2026` or `2026-10`
+    pub fn shard(self, time: DateTime<Utc>) -> String {
+        match self {
+            ShardSize::Year => format!("{:04}", time.year()),
+            ShardSize::Month => format!("{:04}-{:02}", time.year(), time.month()),
+        }
+    }
+}
+
+impl std::str::FromStr for ShardSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "year" => Ok(ShardSize::Year),
+            "month" => Ok(ShardSize::Month),
+            _ => Err(format!("Invalid shard size {}", s)),
+        }
+    }
+}
+
+/// Check if a period starting at `time` belongs in `shard`, whatever the size of the shard.
+///
+/// # Examples
+/// ```
+/// # extern crate chrono;
+/// # extern crate doug;
+/// use chrono::{TimeZone, Utc};
+/// use doug::storage::in_shard;
+///
+/// let time = Utc.ymd(2026, 10, 16).and_hms(9, 0, 0);
+/// assert!(in_shard("2026", time));
+/// assert!(in_shard("2026-10", time));
+/// assert!(!in_shard("2026-09", time));
+/// ```
+pub fn in_shard(shard: &str, time: DateTime<Utc>) -> bool {
+    ShardSize::Month.shard(time).starts_with(shard)
+}
+
+/// A place to load and save periods
+pub trait Storage {
+    /// Load all periods.
+    fn load(&mut self) -> Result<Vec<Period>, String>;
+
+    /// Load the periods needed for `range` that weren't loaded before.
+    ///
+    /// Returns `None` if the backend can only load everything at once with [Storage::load].
+    fn load_range(&mut self, _range: &Range) -> Result<Option<Vec<Period>>, String> {
+        Ok(None)
+    }
+
+    /// Replace the stored periods with `periods`.
+    fn save(&mut self, periods: &[Period]) -> Result<(), String>;
+
+    /// File or directory holding the data.
+    fn location(&self) -> &Path;
+
+    /// Files that saving `periods` replaces, to be backed up first.
+    fn changed_files(&self, _periods: &[Period]) -> Vec<PathBuf> {
+        vec![self.location().to_path_buf()]
+    }
+
+    /// Files that change when `last` changes, to tell if [state::State](crate::state::State)
+    /// is out of date.
+    fn state_files(&self, _last: Option<&Period>) -> Vec<PathBuf> {
+        vec![self.location().to_path_buf()]
+    }
+
+    /// Mark the next save as the result of merging the data file at `source`.
+    fn merging(&mut self, _source: &Path) {}
+
+    /// Rewrite the stored data in its smallest form, returning the number of records removed.
+    fn compact(&mut self, _periods: &[Period]) -> Result<usize, String> {
+        Err("Only journal storage can be compacted".to_string())
+    }
+}
+
+/// Open the backend selected in settings.
+pub fn open(settings: &settings::Settings) -> Result<Box<dyn Storage>, String> {
+    open_kind(settings.storage, settings)
+}
+
+/// Open a backend of `kind` in the data directory from `settings`.
+pub fn open_kind(
+    kind: StorageKind,
+    settings: &settings::Settings,
+) -> Result<Box<dyn Storage>, String> {
+    open_file(&settings.data_location.join(kind.file_name()), settings)
+}
+
+/// Open a data file, picking the backend from its extension. Directories are opened as
+/// [ShardedStorage].
+///
+/// `settings` are used to find the passphrase of encrypted files and the shard size.
+pub fn open_file(
+    location: &Path,
+    settings: &settings::Settings,
+) -> Result<Box<dyn Storage>, String> {
+    if location.is_dir() || location.extension().is_none() {
+        return Ok(Box::new(ShardedStorage::open(
+            location.to_path_buf(),
+            settings.shard_by,
+        )?));
+    }
+    match location
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("sqlite") => Ok(Box::new(SqliteStorage::open(location.to_path_buf())?)),
+        Some("journal") => Ok(Box::new(JournalStorage::new(location.to_path_buf()))),
+        Some("enc") => Ok(Box::new(EncryptedStorage::new(
+            location.to_path_buf(),
+            crypto::passphrase(settings)?,
+        ))),
+        _ => Ok(Box::new(JsonStorage::new(location.to_path_buf()))),
+    }
+}
+
+/// Open a data file like [open_file], but open SQLite databases read-only, so loading
+/// doesn't add the periods table to them. Used for files other than the data file, like
+/// backups and files to compare or merge.
+pub fn open_file_read_only(
+    location: &Path,
+    settings: &settings::Settings,
+) -> Result<Box<dyn Storage>, String> {
+    match location
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("sqlite") if location.is_file() => Ok(Box::new(SqliteStorage::open_read_only(
+            location.to_path_buf(),
+        )?)),
+        _ => open_file(location, settings),
+    }
+}
+
+/// Copies of periods in the data directory `folder` besides the data file.
+///
+/// These are data files of other backends left by
+/// [Doug::migrate_storage](crate::Doug::migrate_storage), the originals kept when migrating
+/// older layouts, and a migration to shards that was interrupted.
+pub fn leftovers(folder: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut leftovers: Vec<PathBuf> = [
+        StorageKind::Json.file_name(),
+        "periods.json",
+        "periods.migrating",
+        StorageKind::Sqlite.file_name(),
+        StorageKind::Journal.file_name(),
+    ]
+    .iter()
+    .map(|name| folder.join(name))
+    .filter(|location| location.exists())
+    .collect();
+    let entries =
+        fs::read_dir(folder).map_err(|err| format!("Couldn't read data directory: {:?}", err))?;
+    let mut backups: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|location| location.to_string_lossy().ends_with(".json-backup"))
+        .collect();
+    backups.sort();
+    leftovers.extend(backups);
+    Ok(leftovers)
+}
+
+/// Load periods from a file or directory returned by [leftovers], without migrating it.
+pub fn load_leftover(
+    location: &Path,
+    settings: &settings::Settings,
+) -> Result<Vec<Period>, String> {
+    if location.is_dir() {
+        let shard_size = settings.shard_by;
+        return ShardedStorage::open(location.to_path_buf(), shard_size)?.load();
+    }
+    match location
+        .extension()
+        .and_then(|extension| extension.to_str())
+    {
+        Some("sqlite") | Some("journal") => open_file_read_only(location, settings)?.load(),
+        _ => {
+            let data_file = fs::File::open(location)
+                .map_err(|err| format!("Couldn't open {:?}: {:?}", location, err))?;
+            Ok(schema::read(data_file)?.periods)
+        }
+    }
+}
+
+/// Periods stored in a versioned JSON file. See [schema].
+pub struct JsonStorage {
+    location: PathBuf,
+}
+
+impl JsonStorage {
+    pub fn new(location: PathBuf) -> Self {
+        JsonStorage { location }
+    }
+}
+
+impl Storage for JsonStorage {
+    /// Load periods, creating the data file if it's missing.
+    ///
+    /// Files in an older layout are migrated. The original file is kept as
+    /// `periods.v<version>.json-backup` before the new layout is written.
+    fn load(&mut self) -> Result<Vec<Period>, String> {
+        let data_file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&self.location)
+            .map_err(|_| format!("Couldn't open datafile: {:?}\n", self.location))?;
+        let loaded = schema::read(&data_file)?;
+        if let Some(version) = loaded.migrated_from {
+            let mut location_backup = self.location.clone();
+            location_backup.set_extension(format!("v{}.json-backup", version));
+            fs::copy(&self.location, &location_backup)
+                .map_err(|err| format!("Couldn't create backup file: {:?}", err))?;
+            self.save(&loaded.periods)?;
+        }
+        Ok(loaded.periods)
+    }
+
+    fn save(&mut self, periods: &[Period]) -> Result<(), String> {
+        let serialized = schema::to_string(periods)?;
+        file::write_atomic(&self.location, serialized.as_bytes())
+    }
+
+    fn location(&self) -> &Path {
+        &self.location
+    }
+}
+
+/// Periods stored in a directory of JSON files, one per year or month. See [ShardSize].
+///
+/// Shards are named after the time the periods in them start, in UTC. Only the shards a
+/// command asks for with [Storage::load_range] are read, and a save only rewrites shards whose
+/// periods changed. Saving periods into a shard that exists but wasn't loaded is refused, since
+/// that would drop the periods already in it.
+///
+/// A data directory with the old single `periods.json` file is migrated the first time it's
+/// opened. The old file is kept as `periods.unsharded.json-backup`.
+pub struct ShardedStorage {
+    location: PathBuf,
+    size: ShardSize,
+    /// Periods of the loaded shards, as they are on disk
+    loaded: BTreeMap<String, Vec<Period>>,
+}
+
+impl ShardedStorage {
+    /// Open the shards in the directory at `location`, migrating a single file data file next
+    /// to it.
+    pub fn open(location: PathBuf, size: ShardSize) -> Result<Self, String> {
+        let single_file = location.with_extension("json");
+        if !location.exists() && single_file.is_file() {
+            ShardedStorage::migrate(&single_file, &location, size)?;
+        }
+        fs::create_dir_all(&location)
+            .map_err(|err| format!("Couldn't create data directory: {:?}", err))?;
+        Ok(ShardedStorage {
+            location,
+            size,
+            loaded: BTreeMap::new(),
+        })
+    }
+
+    /// Split a single file data file into shards.
+    ///
+    /// The shards are written to a temporary directory that's renamed into place, so an
+    /// interrupted migration is started over the next time.
+    fn migrate(single_file: &Path, location: &Path, size: ShardSize) -> Result<(), String> {
+        let periods = JsonStorage::new(single_file.to_path_buf()).load()?;
+        let temp_location = location.with_extension("migrating");
+        if temp_location.exists() {
+            fs::remove_dir_all(&temp_location)
+                .map_err(|err| format!("Couldn't remove {:?}: {:?}", temp_location, err))?;
+        }
+        fs::create_dir_all(&temp_location)
+            .map_err(|err| format!("Couldn't create {:?}: {:?}", temp_location, err))?;
+        let mut shards = ShardedStorage {
+            location: temp_location.clone(),
+            size,
+            loaded: BTreeMap::new(),
+        };
+        shards.save(&periods)?;
+        fs::rename(&temp_location, location)
+            .map_err(|err| format!("Couldn't move shards into place: {:?}", err))?;
+        fs::rename(
+            single_file,
+            single_file.with_extension("unsharded.json-backup"),
+        )
+        .map_err(|err| format!("Couldn't keep single data file as backup: {:?}", err))
+    }
+
+    fn shard_location(&self, shard: &str) -> PathBuf {
+        self.location.join(format!("{}.json", shard))
+    }
+
+    /// Names of the shards on disk, oldest first
+    fn shards(&self) -> Result<Vec<String>, String> {
+        let entries = fs::read_dir(&self.location)
+            .map_err(|err| format!("Couldn't read data directory: {:?}", err))?;
+        let mut shards: Vec<String> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != "json" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .collect();
+        shards.sort();
+        Ok(shards)
+    }
+
+    /// Read a shard unless it's loaded already, returning its periods if it was read.
+    fn read_shard(&mut self, shard: &str) -> Result<Option<Vec<Period>>, String> {
+        if self.loaded.contains_key(shard) {
+            return Ok(None);
+        }
+        let location = self.shard_location(shard);
+        let data_file = fs::File::open(&location)
+            .map_err(|_| format!("Couldn't open datafile: {:?}\n", location))?;
+        let periods = schema::read(&data_file)?.periods;
+        self.loaded.insert(shard.to_string(), periods.clone());
+        Ok(Some(periods))
+    }
+
+    /// Group periods by the shard they're saved in.
+    fn by_shard(&self, periods: &[Period]) -> BTreeMap<String, Vec<Period>> {
+        let mut shards: BTreeMap<String, Vec<Period>> = BTreeMap::new();
+        for period in periods {
+            shards
+                .entry(self.size.shard(period.start_time))
+                .or_default()
+                .push(period.clone());
+        }
+        shards
+    }
+
+    /// Shards that differ from what's on disk after saving `periods`, with their new periods.
+    fn changes(&self, periods: &[Period]) -> BTreeMap<String, Vec<Period>> {
+        let mut shards = self.by_shard(periods);
+        for shard in self.loaded.keys() {
+            shards.entry(shard.clone()).or_default();
+        }
+        shards.retain(|shard, periods| match self.loaded.get(shard) {
+            Some(loaded) => loaded != periods,
+            None => !periods.is_empty(),
+        });
+        shards
+    }
+}
+
+impl Storage for ShardedStorage {
+    fn load(&mut self) -> Result<Vec<Period>, String> {
+        self.load_range(&Range::All)?;
+        Ok(self.loaded.values().flatten().cloned().collect())
+    }
+
+    fn load_range(&mut self, range: &Range) -> Result<Option<Vec<Period>>, String> {
+        let shards = self.shards()?;
+        let mut periods = Vec::new();
+        match *range {
+            Range::All => {
+                for shard in &shards {
+                    periods.extend(self.read_shard(shard)?.unwrap_or_default());
+                }
+            }
+            Range::Between(from, to) | Range::Overlapping(from, to) => {
+                // compare only as much of the time as the shard name holds
+                let from_shard = from.map(|from| ShardSize::Month.shard(from));
+                let to_shard = to.map(|to| ShardSize::Month.shard(to));
+                let mut older = Vec::new();
+                for shard in &shards {
+                    let after_from = from_shard
+                        .as_ref()
+                        .is_none_or(|from| shard.as_str() >= &from[..shard.len().min(from.len())]);
+                    let before_to = to_shard
+                        .as_ref()
+                        .is_none_or(|to| shard.as_str() <= &to[..shard.len().min(to.len())]);
+                    if after_from && before_to {
+                        periods.extend(self.read_shard(shard)?.unwrap_or_default());
+                    } else if !after_from {
+                        older.push(shard);
+                    }
+                }
+                // walk back to the last period before the range, the only one that can run
+                // into it when periods don't overlap
+                if let Range::Overlapping(..) = range {
+                    for shard in older.into_iter().rev() {
+                        periods.extend(self.read_shard(shard)?.unwrap_or_default());
+                        if self
+                            .loaded
+                            .get(shard)
+                            .is_some_and(|loaded| !loaded.is_empty())
+                        {
+                            break;
+                        }
+                    }
+                }
+            }
+            Range::Latest => {
+                let current = self.size.shard(Utc::now());
+                let (older, newer): (Vec<&String>, Vec<&String>) =
+                    shards.iter().partition(|shard| **shard < current);
+                for shard in newer {
+                    periods.extend(self.read_shard(shard)?.unwrap_or_default());
+                }
+                // walk back to the last period, e.g. early in a year without periods yet
+                for shard in older.into_iter().rev() {
+                    if self.loaded.values().any(|periods| !periods.is_empty()) {
+                        break;
+                    }
+                    periods.extend(self.read_shard(shard)?.unwrap_or_default());
+                }
+            }
+        }
+        Ok(Some(periods))
+    }
+
+    fn save(&mut self, periods: &[Period]) -> Result<(), String> {
+        let changes = self.changes(periods);
+        for shard in changes.keys() {
+            if !self.loaded.contains_key(shard) && self.shard_location(shard).exists() {
+                return Err(format!(
+                    "Shard {} wasn't loaded, saving would lose its periods",
+                    shard
+                ));
+            }
+        }
+        for (shard, periods) in changes {
+            let location = self.shard_location(&shard);
+            if periods.is_empty() {
+                fs::remove_file(&location)
+                    .map_err(|err| format!("Couldn't remove {:?}: {:?}", location, err))?;
+            } else {
+                let serialized = schema::to_string(&periods)?;
+                file::write_atomic(&location, serialized.as_bytes())?;
+            }
+            self.loaded.insert(shard, periods);
+        }
+        Ok(())
+    }
+
+    fn location(&self) -> &Path {
+        &self.location
+    }
+
+    fn changed_files(&self, periods: &[Period]) -> Vec<PathBuf> {
+        self.changes(periods)
+            .keys()
+            .map(|shard| self.shard_location(shard))
+            .collect()
+    }
+
+    /// The directory changes whenever a shard is replaced, added or removed, and the shard of
+    /// the last period catches edits in place.
+    fn state_files(&self, last: Option<&Period>) -> Vec<PathBuf> {
+        let mut files = vec![self.location.clone()];
+        if let Some(last) = last {
+            files.push(self.shard_location(&self.size.shard(last.start_time)));
+        }
+        files
+    }
+}
+
+/// Periods stored in a JSON file, encrypted with a passphrase.
+pub struct EncryptedStorage {
+    location: PathBuf,
+    passphrase: String,
+}
+
+impl EncryptedStorage {
+    pub fn new(location: PathBuf, passphrase: String) -> Self {
+        EncryptedStorage {
+            location,
+            passphrase,
+        }
+    }
+}
+
+impl Storage for EncryptedStorage {
+    /// Load periods, creating an empty data file if it's missing.
+    fn load(&mut self) -> Result<Vec<Period>, String> {
+        if !self.location.exists() {
+            self.save(&[])?;
+        }
+        let encrypted = fs::read(&self.location)
+            .map_err(|_| format!("Couldn't open datafile: {:?}\n", self.location))?;
+        let decrypted = crypto::decrypt(&encrypted, &self.passphrase)?;
+        Ok(schema::read(decrypted.as_slice())?.periods)
+    }
+
+    fn save(&mut self, periods: &[Period]) -> Result<(), String> {
+        let serialized = schema::to_string(periods)?;
+        let encrypted = crypto::encrypt(serialized.as_bytes(), &self.passphrase)?;
+        file::write_atomic(&self.location, &encrypted)
+    }
+
+    fn location(&self) -> &Path {
+        &self.location
+    }
+}
+
+/// Version of the SQLite table layout, stored in `PRAGMA user_version`
+const SQLITE_VERSION: i64 = 1;
+
+/// Periods stored in a SQLite database.
+///
+/// Commands load only the periods they need, like with [ShardedStorage]. Saving only writes
+/// periods that changed since they were loaded, and only removes periods that were loaded.
+pub struct SqliteStorage {
+    location: PathBuf,
+    connection: Connection,
+    /// Periods as they are in the database, by id
+    stored: HashMap<String, Period>,
+}
+
+impl SqliteStorage {
+    /// Open a database, creating the periods table if needed.
+    pub fn open(location: PathBuf) -> Result<Self, String> {
+        let connection = Connection::open(&location)
+            .map_err(|err| format!("Couldn't open database {:?}: {}", location, err))?;
+        check_version(&connection)?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS periods (
+                    id TEXT PRIMARY KEY,
+                    project TEXT NOT NULL,
+                    start_time TEXT NOT NULL,
+                    end_time TEXT,
+                    tags TEXT NOT NULL,
+                    note TEXT
+                );
+                CREATE INDEX IF NOT EXISTS periods_start_time ON periods (start_time);
+                PRAGMA user_version = 1;",
+            )
+            .map_err(sqlite_error)?;
+        Ok(SqliteStorage {
+            location,
+            connection,
+            stored: HashMap::new(),
+        })
+    }
+
+    /// Open an existing database without changing it.
+    ///
+    /// Fails if it has no periods table. Saving to it fails too.
+    pub fn open_read_only(location: PathBuf) -> Result<Self, String> {
+        let connection =
+            Connection::open_with_flags(&location, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(|err| format!("Couldn't open database {:?}: {}", location, err))?;
+        check_version(&connection)?;
+        let tables: i64 = connection
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'periods'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(sqlite_error)?;
+        if tables == 0 {
+            return Err(format!(
+                "Database {:?} has no periods table, so it isn't a doug data file",
+                location
+            ));
+        }
+        Ok(SqliteStorage {
+            location,
+            connection,
+            stored: HashMap::new(),
+        })
+    }
+}
+
+impl SqliteStorage {
+    /// Load the periods matching an SQL `condition` that weren't loaded before.
+    fn select(
+        &mut self,
+        condition: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<Period>, String> {
+        let mut statement = self
+            .connection
+            .prepare(&format!(
+                "SELECT id, project, start_time, end_time, tags, note
+                FROM periods WHERE {} ORDER BY start_time",
+                condition
+            ))
+            .map_err(sqlite_error)?;
+        let rows = statement
+            .query_map(params, |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                ))
+            })
+            .map_err(sqlite_error)?;
+        let mut periods = Vec::new();
+        for row in rows {
+            let (id, project, start_time, end_time, tags, note) = row.map_err(sqlite_error)?;
+            if self.stored.contains_key(&id) {
+                continue;
+            }
+            periods.push(Period {
+                id,
+                project,
+                start_time: parse_time(&start_time)?,
+                end_time: end_time.as_deref().map(parse_time).transpose()?,
+                tags: serde_json::from_str(&tags)
+                    .map_err(|err| format!("There was a serialization issue: {:?}\n", err))?,
+                note,
+            });
+        }
+        self.stored.extend(
+            periods
+                .iter()
+                .map(|period| (period.id.clone(), period.clone())),
+        );
+        Ok(periods)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&mut self) -> Result<Vec<Period>, String> {
+        self.stored.clear();
+        self.select("1", &[])
+    }
+
+    /// Load the periods in `range` with a query, so the rest of the database isn't read.
+    ///
+    /// Times are stored as RFC 3339 text in UTC, which sorts the same way as the times.
+    fn load_range(&mut self, range: &Range) -> Result<Option<Vec<Period>>, String> {
+        let periods = match *range {
+            Range::All => self.select("1", &[])?,
+            Range::Between(from, to) => {
+                let from = from.map(|from| from.to_rfc3339());
+                let to = to.map(|to| to.to_rfc3339());
+                self.select(
+                    "(?1 IS NULL OR start_time >= ?1) AND (?2 IS NULL OR start_time <= ?2)",
+                    params![from, to],
+                )?
+            }
+            Range::Overlapping(from, to) => {
+                let from = from.map(|from| from.to_rfc3339());
+                let to = to.map(|to| to.to_rfc3339());
+                self.select(
+                    "(?2 IS NULL OR start_time < ?2)
+                    AND (?1 IS NULL OR end_time IS NULL OR end_time > ?1)",
+                    params![from, to],
+                )?
+            }
+            Range::Latest => {
+                self.select("start_time >= (SELECT MAX(start_time) FROM periods)", &[])?
+            }
+        };
+        Ok(Some(periods))
+    }
+
+    fn save(&mut self, periods: &[Period]) -> Result<(), String> {
+        let transaction = self.connection.transaction().map_err(sqlite_error)?;
+        {
+            let mut upsert = transaction
+                .prepare(
+                    "INSERT OR REPLACE INTO periods (id, project, start_time, end_time, tags, note)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .map_err(sqlite_error)?;
+            for period in periods {
+                if self.stored.get(&period.id) == Some(period) {
+                    continue;
+                }
+                let tags = serde_json::to_string(&period.tags)
+                    .map_err(|_| "Couldn't serialize data to string".to_string())?;
+                upsert
+                    .execute(params![
+                        period.id,
+                        period.project,
+                        period.start_time.to_rfc3339(),
+                        period.end_time.map(|time| time.to_rfc3339()),
+                        tags,
+                        period.note,
+                    ])
+                    .map_err(sqlite_error)?;
+            }
+
+            let mut delete = transaction
+                .prepare("DELETE FROM periods WHERE id = ?1")
+                .map_err(sqlite_error)?;
+            let ids: std::collections::HashSet<&str> =
+                periods.iter().map(|period| period.id.as_str()).collect();
+            for id in self.stored.keys().filter(|id| !ids.contains(id.as_str())) {
+                delete.execute(params![id]).map_err(sqlite_error)?;
+            }
+        }
+        transaction.commit().map_err(sqlite_error)?;
+        self.stored = periods
+            .iter()
+            .map(|period| (period.id.clone(), period.clone()))
+            .collect();
+        Ok(())
+    }
+
+    fn location(&self) -> &Path {
+        &self.location
+    }
+
+    /// Saving only writes changed rows, so the database is backed up before rare changes like
+    /// restoring a backup rather than on every save.
+    fn changed_files(&self, _periods: &[Period]) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+/// Refuse databases written by a newer version of doug.
+fn check_version(connection: &Connection) -> Result<(), String> {
+    let version: i64 = connection
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(sqlite_error)?;
+    if version > SQLITE_VERSION {
+        return Err(format!(
+            "Database has version {}, but this doug only supports up to version {}. Please upgrade doug.\n",
+            version, SQLITE_VERSION
+        ));
+    }
+    Ok(())
+}
+
+fn parse_time(time: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| format!("Couldn't parse date {} from database", time))
+}
+
+fn sqlite_error(error: rusqlite::Error) -> String {
+    format!("Database error: {}", error)
+}
@@ -0,0 +1,208 @@
+//! Undo and redo of changes to periods.
+//!
+//! Every save records the periods it changed as an [Operation] in `undo.json` in the data
+//! directory. Undoing an operation puts the periods back the way they were, and moves the
+//! operation to the redo stack. Making a new change clears the redo stack.
+//!
+//! So that a save doesn't rewrite the whole history, new operations are appended to
+//! `undo.log`, one per line, and folded into `undo.json` once the log grows past [LOG_LIMIT] or
+//! an operation is undone or redone.
+//!
+//! The history holds copies of periods, so it's encrypted along with an encrypted data file.
+//! An encrypted history has no log and is rewritten on every save, like the data file.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{crypto, file, Period};
+
+/// Number of operations that can be undone
+pub const LIMIT: usize = 100;
+
+/// Size in bytes of `undo.log` past which it's folded into `undo.json`
+pub const LOG_LIMIT: u64 = 64 * 1024;
+
+/// A change to periods, with a description of the command that made it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Operation {
+    pub description: String,
+    pub at: DateTime<Utc>,
+    /// Periods that were changed or removed, as they were before the change
+    pub before: Vec<Period>,
+    /// Periods that were changed or added, as they are after the change
+    pub after: Vec<Period>,
+}
+
+impl Operation {
+    /// Find the periods that differ between `old` and `new`, matching periods by id.
+    pub fn new(description: &str, old: &[Period], new: &[Period]) -> Self {
+        let old_by_id: HashMap<&str, &Period> = old
+            .iter()
+            .map(|period| (period.id.as_str(), period))
+            .collect();
+        let new_by_id: HashMap<&str, &Period> = new
+            .iter()
+            .map(|period| (period.id.as_str(), period))
+            .collect();
+        Operation {
+            description: description.to_string(),
+            at: Utc::now(),
+            before: old
+                .iter()
+                .filter(|period| new_by_id.get(period.id.as_str()) != Some(period))
+                .cloned()
+                .collect(),
+            after: new
+                .iter()
+                .filter(|period| old_by_id.get(period.id.as_str()) != Some(period))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.before.is_empty() && self.after.is_empty()
+    }
+
+    /// The operation that undoes this one
+    pub fn reversed(&self) -> Self {
+        Operation {
+            before: self.after.clone(),
+            after: self.before.clone(),
+            ..self.clone()
+        }
+    }
+
+    /// Replace the `before` periods with the `after` periods.
+    ///
+    /// Fails without changing anything if one of the `before` periods was changed or removed
+    /// since the operation was recorded.
+    pub fn apply(&self, periods: &mut Vec<Period>) -> Result<(), String> {
+        for expected in &self.before {
+            if !periods.contains(expected) {
+                return Err(format!(
+                    "Period {} was changed since \"{}\"",
+                    expected.id, self.description
+                ));
+            }
+        }
+        periods.retain(|period| !self.before.contains(period));
+        periods.extend(self.after.iter().cloned());
+        periods.sort_by_key(|period| period.start_time);
+        Ok(())
+    }
+}
+
+/// Operations that can be undone and redone, most recent last
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct History {
+    pub undo: Vec<Operation>,
+    pub redo: Vec<Operation>,
+}
+
+impl History {
+    /// Load the history of a data directory, including the operations in the log. A missing
+    /// history is empty.
+    ///
+    /// # Arguments
+    /// * `passphrase` — passphrase for an encrypted history.
+    pub fn load(folder: &Path, passphrase: Option<&str>) -> Result<Self, String> {
+        let location = location(folder);
+        let mut history = if location.exists() {
+            let mut contents = fs::read(&location)
+                .map_err(|err| format!("Couldn't read undo history: {:?}", err))?;
+            if crypto::is_encrypted(&contents) {
+                let passphrase =
+                    passphrase.ok_or_else(|| "The undo history is encrypted".to_string())?;
+                contents = crypto::decrypt(&contents, passphrase)?;
+            }
+            serde_json::from_slice(&contents)
+                .map_err(|err| format!("There was a serialization issue: {:?}\n", err))?
+        } else {
+            History::default()
+        };
+        let log_location = log_location(folder);
+        if log_location.exists() {
+            let log = fs::read_to_string(&log_location)
+                .map_err(|err| format!("Couldn't read undo log: {:?}", err))?;
+            // a line without a line break was cut off while it was written
+            for line in log
+                .split_inclusive('\n')
+                .filter(|line| line.ends_with('\n'))
+            {
+                let operation = serde_json::from_str(line)
+                    .map_err(|err| format!("There was a serialization issue: {:?}\n", err))?;
+                history.record(operation);
+            }
+        }
+        Ok(history)
+    }
+
+    /// Save the history, encrypted if there's a `passphrase`, and empty the log.
+    pub fn save(&self, folder: &Path, passphrase: Option<&str>) -> Result<(), String> {
+        let mut serialized = serde_json::to_vec(self)
+            .map_err(|_| "Couldn't serialize data to string".to_string())?;
+        if let Some(passphrase) = passphrase {
+            serialized = crypto::encrypt(&serialized, passphrase)?;
+        }
+        file::write_atomic(&location(folder), &serialized)?;
+        let log_location = log_location(folder);
+        match passphrase {
+            // the log is never encrypted
+            Some(_) if log_location.exists() => file::remove_securely(&log_location)?,
+            None if log_location.exists() => fs::remove_file(&log_location)
+                .map_err(|err| format!("Couldn't remove {:?}: {:?}", log_location, err))?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Record a new operation in the history of a data directory.
+    ///
+    /// The operation is appended to the log, unless the history is encrypted or the log is
+    /// full. Then the whole history is loaded and saved.
+    pub fn append(
+        folder: &Path,
+        passphrase: Option<&str>,
+        operation: Operation,
+    ) -> Result<(), String> {
+        let log_location = log_location(folder);
+        let log_len = fs::metadata(&log_location).map_or(0, |metadata| metadata.len());
+        if passphrase.is_some() || log_len > LOG_LIMIT {
+            let mut history = History::load(folder, passphrase)?;
+            history.record(operation);
+            return history.save(folder, passphrase);
+        }
+        let mut line = serde_json::to_vec(&operation)
+            .map_err(|_| "Couldn't serialize data to string".to_string())?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_location)
+            .and_then(|mut log| log.write_all(&line).and_then(|_| log.sync_all()))
+            .map_err(|err| format!("Couldn't write undo log: {:?}", err))
+    }
+
+    /// Add a new operation, forgetting operations that were undone and the oldest ones past
+    /// [LIMIT].
+    pub fn record(&mut self, operation: Operation) {
+        self.redo.clear();
+        self.undo.push(operation);
+        let excess = self.undo.len().saturating_sub(LIMIT);
+        self.undo.drain(..excess);
+    }
+}
+
+fn location(folder: &Path) -> PathBuf {
+    folder.join("undo.json")
+}
+
+fn log_location(folder: &Path) -> PathBuf {
+    folder.join("undo.log")
+}